@@ -0,0 +1,46 @@
+//! Original scenario of the prototype: receive until the ring is full,
+//! release every packet, then receive again from the freed slots.
+
+use rust_nethuns_miri::{RecvPacket, Socket};
+
+
+fn main() {
+    let socket = Socket::new();
+
+    let mut v: Vec<RecvPacket> = vec![];
+
+    // Receive as many packets as possible,
+    // so that every slot is set as NOT FREE
+    while let Ok(packet) = socket.recv() {
+        v.push(packet);
+    }
+    for pkt in &v {
+        println!("{}", pkt);
+    }
+
+    // Drop all received packets
+    release_all(&mut v);
+
+    // Receive as many packets as possible,
+    // so that every slot is set as NOT FREE.
+    // If the Drop trait was correctly implemented,
+    // this loop should have the same output as the previous.
+    while let Ok(packet) = socket.recv() {
+        v.push(packet);
+    }
+    for pkt in &v {
+        println!("{}", pkt);
+    }
+
+    release_all(&mut v);
+}
+
+
+/// Drop every packet in `v`, reporting which slot is released.
+fn release_all(v: &mut Vec<RecvPacket>) {
+    for pkt in v.drain(..) {
+        let idx = pkt.idx();
+        drop(pkt);
+        println!("drop packet {}", idx);
+    }
+}
@@ -0,0 +1,130 @@
+//! Contiguous frame storage shared by the slots of a ring.
+
+use std::alloc::{self, Layout};
+use std::ptr::NonNull;
+use std::slice;
+
+
+/// Single allocation holding the frames of every slot of a ring,
+/// like the buffers mapped by AF_PACKET, AF_XDP and netmap.
+///
+/// Frame `idx` starts at `idx * stride()`, where the stride is the frame
+/// size rounded up to the frame alignment, so every frame is aligned.
+/// Frames are handed out as disjoint borrows of the one allocation,
+/// which lets Miri check the aliasing of packets held at the same time.
+#[derive(Debug)]
+pub struct FrameArena {
+    ptr: NonNull<u8>,
+    layout: Layout,
+    num_frames: usize,
+    frame_size: usize,
+    stride: usize,
+}
+
+// SAFETY: the arena owns plain bytes, and the rings only hand out
+// borrows of the frames they own according to the slot status.
+unsafe impl Send for FrameArena {}
+unsafe impl Sync for FrameArena {}
+
+impl FrameArena {
+    /// Allocate `num_frames` zeroed frames of `frame_size` bytes,
+    /// each aligned to `align` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `num_frames` or `frame_size` is zero, if `align` is
+    /// not a power of two, or if the arena size overflows.
+    pub fn new(num_frames: usize, frame_size: usize, align: usize) -> Self {
+        assert!(num_frames > 0 && frame_size > 0, "empty frame arena");
+        assert!(align.is_power_of_two(), "bad frame alignment {align}");
+        let stride = frame_size.next_multiple_of(align);
+        let layout = stride
+            .checked_mul(num_frames)
+            .and_then(|size| Layout::from_size_align(size, align).ok())
+            .expect("frame arena too large");
+
+        // SAFETY: the layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout)
+        };
+        FrameArena {
+            ptr,
+            layout,
+            num_frames,
+            frame_size,
+            stride,
+        }
+    }
+
+    /// Number of frames.
+    pub fn len(&self) -> usize {
+        self.num_frames
+    }
+
+    /// Check whether the arena has no frames (never true).
+    pub fn is_empty(&self) -> bool {
+        self.num_frames == 0
+    }
+
+    /// Size in bytes of each frame.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Distance in bytes between the starts of two consecutive frames.
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Alignment of each frame.
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+
+    /// Start of the arena.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Start of frame `idx`.
+    fn frame_ptr(&self, idx: usize) -> *mut u8 {
+        assert!(idx < self.num_frames);
+        // SAFETY: `idx` is in bounds, so the frame lies
+        // within the allocation.
+        unsafe { self.ptr.as_ptr().add(idx * self.stride) }
+    }
+
+    /// Shared borrow of frame `idx`.
+    ///
+    /// # Safety
+    ///
+    /// No mutable borrow of the frame may be alive
+    /// for the lifetime of the returned slice.
+    pub(crate) unsafe fn frame(&self, idx: usize) -> &[u8] {
+        slice::from_raw_parts(self.frame_ptr(idx), self.frame_size)
+    }
+
+    /// Mutable borrow of frame `idx`.
+    ///
+    /// The borrow is derived from the allocation pointer, not from
+    /// `self`, so it only covers frame `idx` and leaves the borrows
+    /// of the other frames valid.
+    ///
+    /// # Safety
+    ///
+    /// No other borrow of the frame may be alive
+    /// for the lifetime of the returned slice.
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn frame_mut(&self, idx: usize) -> &mut [u8] {
+        slice::from_raw_parts_mut(self.frame_ptr(idx), self.frame_size)
+    }
+}
+
+impl Drop for FrameArena {
+    fn drop(&mut self) {
+        // SAFETY: the arena was allocated with `self.layout`,
+        // and no frame borrow outlives the arena.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
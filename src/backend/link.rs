@@ -0,0 +1,365 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::backend::Backend;
+use crate::error::RecvError;
+use crate::packet::PacketHeader;
+use crate::stats::Stats;
+
+
+/// Default number of frames a direction of a link can buffer.
+pub const DEFAULT_LINK_CAPACITY: usize = 1024;
+
+
+/// In-memory link which connects two sockets in the same process.
+///
+/// The link is made of two [`LinkBackend`]s: every frame flushed by
+/// the socket over one end shows up in `recv` on the socket over the
+/// other end, in order. Each direction buffers up to a fixed number
+/// of frames: frames flushed while the buffer is full are dropped.
+///
+/// The link can also emulate a faulty network, see [`Impairments`].
+///
+/// ```
+/// use rust_nethuns_miri::{Socket, SocketOptions, VirtualLink};
+///
+/// let (a, b) = VirtualLink::new().pair();
+/// let a = Socket::with_backend(SocketOptions::default(), a).unwrap();
+/// let b = Socket::with_backend(SocketOptions::default(), b).unwrap();
+///
+/// a.send(&[1, 2, 3]).unwrap();
+/// a.flush();
+/// assert_eq!(b.recv().unwrap().packet(), &[1, 2, 3]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct VirtualLink {
+    capacity: usize,
+    impairments: Impairments,
+}
+
+impl VirtualLink {
+    /// Create a link whose directions can buffer
+    /// [`DEFAULT_LINK_CAPACITY`] frames each.
+    pub fn new() -> Self {
+        VirtualLink {
+            capacity: DEFAULT_LINK_CAPACITY,
+            impairments: Impairments::default(),
+        }
+    }
+
+    /// Set how many frames each direction can buffer.
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Apply `impairments` to both directions of the link.
+    pub fn with_impairments(mut self, impairments: Impairments) -> Self {
+        self.impairments = impairments;
+        self
+    }
+
+    /// Create the two ends of the link.
+    ///
+    /// Each direction draws from its own random generator, so the
+    /// frames reaching an end only depend on the seed and on the
+    /// frames sent by the other end.
+    pub fn pair(&self) -> (LinkBackend, LinkBackend) {
+        let epoch = Instant::now();
+        let wire = |direction| {
+            Arc::new(Mutex::new(Wire::new(
+                self.capacity,
+                self.impairments.clone(),
+                SplitMix64::new(self.impairments.seed ^ direction),
+            )))
+        };
+        let a_to_b = wire(0);
+        let b_to_a = wire(u64::MAX);
+        (
+            LinkBackend::new(Arc::clone(&b_to_a), Arc::clone(&a_to_b), epoch),
+            LinkBackend::new(a_to_b, b_to_a, epoch),
+        )
+    }
+}
+
+impl Default for VirtualLink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Faults injected by a [`VirtualLink`] into the frames it carries.
+///
+/// Probabilities are in `0.0..=1.0` and are drawn from a generator
+/// seeded with `seed`, so that a run can be reproduced exactly.
+/// Only the delivery times depend on the wall clock. The default
+/// is a perfect link.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Impairments {
+    /// Seed of the random generator
+    pub seed: u64,
+    /// Probability of a frame being lost
+    pub loss: f64,
+    /// Probability of a frame being delivered twice
+    pub duplicate: f64,
+    /// Probability of a frame overtaking the previous
+    /// frame still on the link
+    pub reorder: f64,
+    /// Probability of a random bit of the frame being flipped
+    pub corrupt: f64,
+    /// Time each frame spends on the link
+    pub delay: Duration,
+    /// Maximum random deviation from `delay`, in both directions
+    pub jitter: Duration,
+    /// Link bandwidth in bytes per second, unlimited if `None`
+    pub rate: Option<u64>,
+}
+
+
+/// Frame travelling on a link.
+#[derive(Debug)]
+struct Frame {
+    data: Vec<u8>,
+    /// Time the frame reaches the other end, since the link epoch
+    arrival: Duration,
+}
+
+
+/// One direction of a link.
+///
+/// Frames are kept sorted by arrival time.
+#[derive(Debug)]
+struct Wire {
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    /// Whether the transmitting end has been closed
+    closed: bool,
+    impairments: Impairments,
+    rng: SplitMix64,
+    /// Time the link finishes serializing the last frame
+    busy_until: Duration,
+}
+
+impl Wire {
+    fn new(capacity: usize, impairments: Impairments, rng: SplitMix64) -> Self {
+        Wire {
+            frames: VecDeque::new(),
+            capacity,
+            closed: false,
+            impairments,
+            rng,
+            busy_until: Duration::ZERO,
+        }
+    }
+
+    /// Put `data`, transmitted at `now`, on the wire.
+    fn push(&mut self, mut data: Vec<u8>, now: Duration) -> io::Result<()> {
+        if self.frames.len() >= self.capacity {
+            return Err(io::Error::new(
+                ErrorKind::WouldBlock,
+                "the link buffer is full",
+            ));
+        }
+
+        // Lost frames still take their time on the wire
+        let departure = self.serialize(data.len(), now);
+        if self.rng.chance(self.impairments.loss) {
+            return Ok(());
+        }
+        if !data.is_empty() && self.rng.chance(self.impairments.corrupt) {
+            let bit = self.rng.below(data.len() as u64 * 8) as usize;
+            data[bit / 8] ^= 1 << (bit % 8);
+        }
+        let duplicate = self.rng.chance(self.impairments.duplicate)
+            && self.frames.len() + 1 < self.capacity;
+        let reorder = self.rng.chance(self.impairments.reorder);
+
+        let mut arrival = departure + self.delay();
+        if reorder {
+            // Overtake the previous frame by taking its arrival time
+            if let Some(last) = self.frames.back() {
+                arrival = arrival.min(last.arrival);
+            }
+        }
+        let frame = Frame { data, arrival };
+        if duplicate {
+            let copy = Frame {
+                data: frame.data.clone(),
+                ..frame
+            };
+            self.insert(copy, false);
+        }
+        self.insert(frame, reorder);
+        Ok(())
+    }
+
+    /// Account for the time needed to put `len` bytes on the wire,
+    /// returning the time the last byte leaves.
+    fn serialize(&mut self, len: usize, now: Duration) -> Duration {
+        let start = self.busy_until.max(now);
+        self.busy_until = match self.impairments.rate {
+            Some(rate) if rate > 0 => {
+                start + Duration::from_secs_f64(len as f64 / rate as f64)
+            }
+            _ => start,
+        };
+        self.busy_until
+    }
+
+    /// Propagation delay of the next frame, jitter included.
+    fn delay(&mut self) -> Duration {
+        let Impairments { delay, jitter, .. } = self.impairments;
+        if jitter.is_zero() {
+            return delay;
+        }
+        let deviation = jitter.mul_f64(self.rng.unit());
+        if self.rng.chance(0.5) {
+            delay + deviation
+        } else {
+            delay.saturating_sub(deviation)
+        }
+    }
+
+    /// Insert `frame` by arrival time, ahead of the frames arriving
+    /// at the same time if `ahead` is set, behind them otherwise.
+    fn insert(&mut self, frame: Frame, ahead: bool) {
+        let pos = self.frames.partition_point(|queued| {
+            if ahead {
+                queued.arrival < frame.arrival
+            } else {
+                queued.arrival <= frame.arrival
+            }
+        });
+        self.frames.insert(pos, frame);
+    }
+
+    /// Take the next frame which has reached the other end by `now`.
+    fn pop(&mut self, now: Duration) -> Option<Frame> {
+        match self.frames.front() {
+            Some(frame) if frame.arrival <= now => self.frames.pop_front(),
+            _ => None,
+        }
+    }
+}
+
+
+/// SplitMix64 pseudo-random generator: small, fast and
+/// good enough to drive the link impairments.
+#[derive(Debug, Clone)]
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        SplitMix64(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0.0..1.0`.
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Uniform value in `0..n`, with `n` greater than zero.
+    fn below(&mut self, n: u64) -> u64 {
+        ((self.next() as u128 * n as u128) >> 64) as u64
+    }
+
+    /// Whether an event of probability `p` happens.
+    fn chance(&mut self, p: f64) -> bool {
+        // Always draw, so that the sequence does not depend on `p`
+        self.unit() < p
+    }
+}
+
+
+/// End of a [`VirtualLink`], to be used as the backend of a socket.
+///
+/// The timestamp of a received frame is its arrival time, measured
+/// from the creation of the link. Once the other end has been closed
+/// and every frame it sent has been received, receives fail with
+/// [`RecvError::Closed`]. Frames larger than the ring frame size are
+/// dropped, and receives fail with [`RecvError::Truncated`].
+#[derive(Debug)]
+pub struct LinkBackend {
+    rx: Arc<Mutex<Wire>>,
+    tx: Arc<Mutex<Wire>>,
+    epoch: Instant,
+    stats: Stats,
+}
+
+impl LinkBackend {
+    fn new(rx: Arc<Mutex<Wire>>, tx: Arc<Mutex<Wire>>, epoch: Instant) -> Self {
+        LinkBackend {
+            rx,
+            tx,
+            epoch,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Number of frames on their way to this end,
+    /// including the ones which have not arrived yet.
+    pub fn pending(&self) -> usize {
+        lock(&self.rx).frames.len()
+    }
+}
+
+impl Backend for LinkBackend {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        let mut rx = lock(&self.rx);
+        let Some(next) = rx.pop(self.epoch.elapsed()) else {
+            return Err(if rx.closed && rx.frames.is_empty() {
+                RecvError::Closed
+            } else {
+                RecvError::WouldBlock
+            });
+        };
+        drop(rx);
+
+        if next.data.len() > frame.len() {
+            self.stats.rx_dropped += 1;
+            return Err(RecvError::Truncated {
+                len: next.data.len(),
+                max: frame.len(),
+            });
+        }
+        frame[..next.data.len()].copy_from_slice(&next.data);
+        header.timestamp = next.arrival;
+        header.caplen = next.data.len();
+        header.len = next.data.len();
+        Ok(())
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        lock(&self.tx).push(frame.to_vec(), self.epoch.elapsed())
+    }
+
+    fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn close(&mut self) {
+        lock(&self.tx).closed = true;
+    }
+}
+
+
+fn lock(wire: &Mutex<Wire>) -> MutexGuard<'_, Wire> {
+    // A wire is never left in an inconsistent state,
+    // so a poisoned lock can be reused
+    wire.lock().unwrap_or_else(|err| err.into_inner())
+}
@@ -0,0 +1,55 @@
+use std::io;
+use std::time::Instant;
+
+use crate::backend::Backend;
+use crate::error::RecvError;
+use crate::packet::PacketHeader;
+
+
+/// Synthetic in-memory backend, used by default.
+///
+/// Every packet fills the whole frame with the bytes
+/// `0, 1, 2, ...` (wrapping at 256), and transmitted
+/// frames are discarded.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    /// Creation time, used as the epoch of packet timestamps
+    epoch: Instant,
+}
+
+impl MemoryBackend {
+    /// Create a new synthetic backend.
+    pub fn new() -> Self {
+        MemoryBackend {
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        // Mutate the packet buffer to test if it's safe to mutate
+        for (i, byte) in frame.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        header.timestamp = self.epoch.elapsed();
+        header.caplen = frame.len();
+        header.len = frame.len();
+        Ok(())
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> io::Result<()> {
+        // The synthetic backend has no wire
+        Ok(())
+    }
+}
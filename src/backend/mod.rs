@@ -0,0 +1,127 @@
+//! Packet sources and sinks which a [`Socket`](crate::Socket)
+//! can run over.
+
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::error::RecvError;
+use crate::options::{SocketOptions, MAX_FRAME_SIZE};
+use crate::packet::PacketHeader;
+use crate::stats::Stats;
+
+mod link;
+mod memory;
+mod pcap;
+mod pcapng;
+mod udp;
+#[cfg(unix)]
+mod unix;
+
+pub use link::{Impairments, LinkBackend, VirtualLink, DEFAULT_LINK_CAPACITY};
+pub use memory::MemoryBackend;
+pub use pcap::{PcapBackend, PCAP_MAGIC_NSEC, PCAP_MAGIC_USEC};
+pub use pcapng::{PcapNgBackend, PcapNgInterface};
+pub use udp::UdpBackend;
+#[cfg(unix)]
+pub use unix::UnixDatagramBackend;
+
+
+/// Source of the packets received by a socket and sink of the frames
+/// it transmits.
+///
+/// The socket owns the rings and the slot lifecycle: a backend only
+/// moves bytes in and out of the slots it is handed over, i.e. the
+/// slots in state [`SlotState::Backend`](crate::SlotState::Backend).
+///
+/// The socket borrows its rings mutably while it runs a backend method,
+/// so a backend must not use the socket which owns it (e.g. through
+/// a handle stored in the backend): the socket panics if re-entered.
+pub trait Backend {
+    /// Prepare the backend for a socket created with options `opts`.
+    fn open(&mut self, opts: &SocketOptions) -> io::Result<()> {
+        let _ = opts;
+        Ok(())
+    }
+
+    /// Fill `frame`, which is as large as the ring frame size,
+    /// with the next packet and describe it in `header`.
+    ///
+    /// `header` is reset to its default value before every call, and
+    /// `header.caplen` must not exceed `frame.len()`. Fails with
+    /// [`RecvError::WouldBlock`] if no packet is available yet and with
+    /// [`RecvError::Closed`] if the packet source is exhausted.
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError>;
+
+    /// Transmit `frame`.
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()>;
+
+    /// Counters collected by the backend itself,
+    /// which are added to the ones collected by the rings.
+    fn stats(&self) -> Stats {
+        Stats::default()
+    }
+
+    /// Release the resources of the backend.
+    /// Called when the socket is dropped.
+    fn close(&mut self) {}
+}
+
+
+/// Map an I/O error of a nonblocking socket to a receive error.
+pub(crate) fn recv_error(err: io::Error) -> RecvError {
+    match err.kind() {
+        ErrorKind::WouldBlock | ErrorKind::Interrupted => RecvError::WouldBlock,
+        _ => RecvError::Backend(err),
+    }
+}
+
+/// Wall-clock time, used to timestamp live packets.
+pub(crate) fn wall_clock() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+
+/// Receive buffer of the datagram backends, one byte larger than the
+/// largest frame, so that datagrams which do not fit their frame are
+/// detected instead of being silently truncated by the kernel.
+pub(crate) struct DatagramBuf(Box<[u8]>);
+
+impl DatagramBuf {
+    pub(crate) fn new() -> Self {
+        DatagramBuf(vec![0; MAX_FRAME_SIZE + 1].into_boxed_slice())
+    }
+
+    /// Receive a datagram through `recv`, store as much of it as fits
+    /// into `frame` and describe it in `header`.
+    ///
+    /// A datagram larger than `frame` has a `caplen` smaller than its
+    /// `len`. Datagrams larger than the buffer itself, which only unix
+    /// sockets can carry, report a `len` of `MAX_FRAME_SIZE + 1`.
+    pub(crate) fn recv(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+        recv: impl FnOnce(&mut [u8]) -> io::Result<usize>,
+    ) -> Result<(), RecvError> {
+        let len = recv(&mut self.0).map_err(recv_error)?;
+        let caplen = len.min(frame.len());
+        frame[..caplen].copy_from_slice(&self.0[..caplen]);
+        header.timestamp = wall_clock();
+        header.caplen = caplen;
+        header.len = len;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for DatagramBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("DatagramBuf").field(&self.0.len()).finish()
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::backend::Backend;
+use crate::error::RecvError;
+use crate::packet::PacketHeader;
+
+
+/// Magic number of classic pcap files with microsecond timestamps.
+pub const PCAP_MAGIC_USEC: u32 = 0xa1b2_c3d4;
+
+/// Magic number of classic pcap files with nanosecond timestamps.
+pub const PCAP_MAGIC_NSEC: u32 = 0xa1b2_3c4d;
+
+
+/// Backend which replays the packets of a classic libpcap file.
+///
+/// Both microsecond and nanosecond files are supported, in either byte
+/// order. Packet timestamps are relative to the Unix epoch. Packets
+/// longer than the snapshot length or than the ring frame size are
+/// truncated: `caplen` is the number of bytes stored in the slot,
+/// while `len` is always the original length of the packet.
+///
+/// Once the whole file has been replayed, receives fail with
+/// [`RecvError::Closed`]. The backend cannot transmit.
+#[derive(Debug)]
+pub struct PcapBackend<R> {
+    reader: R,
+    big_endian: bool,
+    nanosecond: bool,
+    snaplen: usize,
+    linktype: u32,
+}
+
+impl PcapBackend<BufReader<File>> {
+    /// Open the pcap file at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapBackend<R> {
+    /// Read the pcap global header from `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 24];
+        reader.read_exact(&mut header)?;
+
+        let magic = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        let (big_endian, nanosecond) = match magic {
+            PCAP_MAGIC_USEC => (false, false),
+            PCAP_MAGIC_NSEC => (false, true),
+            _ if magic.swap_bytes() == PCAP_MAGIC_USEC => (true, false),
+            _ if magic.swap_bytes() == PCAP_MAGIC_NSEC => (true, true),
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("not a pcap file (magic number {magic:#010x})"),
+                ))
+            }
+        };
+
+        let mut backend = PcapBackend {
+            reader,
+            big_endian,
+            nanosecond,
+            snaplen: 0,
+            linktype: 0,
+        };
+        backend.snaplen = backend.u32_at(&header, 16) as usize;
+        backend.linktype = backend.u32_at(&header, 20);
+        Ok(backend)
+    }
+
+    /// Truncate packets to `snaplen` bytes,
+    /// instead of the snapshot length of the file.
+    pub fn with_snaplen(mut self, snaplen: usize) -> Self {
+        self.snaplen = snaplen;
+        self
+    }
+
+    /// Snapshot length.
+    pub fn snaplen(&self) -> usize {
+        self.snaplen
+    }
+
+    /// Link-layer header type of the packets (e.g. 1 for Ethernet).
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+
+    /// Whether timestamps have nanosecond resolution.
+    pub fn is_nanosecond(&self) -> bool {
+        self.nanosecond
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let bytes = buf[offset..offset + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+impl<R: Read> Backend for PcapBackend<R> {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        let mut record = [0; 16];
+        if !read_exact_or_eof(&mut self.reader, &mut record)? {
+            return Err(RecvError::Closed);
+        }
+
+        let ts_sec = self.u32_at(&record, 0);
+        let ts_frac = self.u32_at(&record, 4);
+        let incl_len = self.u32_at(&record, 8) as usize;
+        let orig_len = self.u32_at(&record, 12) as usize;
+
+        // Read the stored bytes straight into the slot,
+        // and skip the ones which do not fit
+        let caplen = incl_len.min(self.snaplen).min(frame.len());
+        self.reader.read_exact(&mut frame[..caplen])?;
+        skip(&mut self.reader, incl_len - caplen)?;
+
+        let nanos = if self.nanosecond {
+            ts_frac
+        } else {
+            ts_frac.saturating_mul(1000)
+        };
+        header.timestamp = Duration::from_secs(ts_sec as u64)
+            + Duration::from_nanos(nanos as u64);
+        header.caplen = caplen;
+        header.len = orig_len.max(incl_len);
+        Ok(())
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "pcap replay sockets cannot transmit",
+        ))
+    }
+}
+
+
+/// Fill `buf` from `reader`.
+///
+/// Returns `false` if `reader` was already at end of file,
+/// and fails if it ends halfway through `buf`.
+pub(crate) fn read_exact_or_eof(
+    reader: &mut impl Read,
+    buf: &mut [u8],
+) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(true)
+}
+
+/// Discard the next `len` bytes of `reader`.
+pub(crate) fn skip(reader: &mut impl Read, len: usize) -> io::Result<()> {
+    let skipped = io::copy(&mut reader.take(len as u64), &mut io::sink())?;
+    if skipped < len as u64 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
@@ -0,0 +1,443 @@
+use std::fs::File;
+use std::io::{self, BufReader, ErrorKind, Read};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::backend::pcap::{read_exact_or_eof, skip};
+use crate::backend::Backend;
+use crate::error::RecvError;
+use crate::packet::{Direction, PacketHeader};
+use crate::writer::{
+    padded, PCAPNG_BYTE_ORDER_MAGIC, PCAPNG_EPB, PCAPNG_IDB, PCAPNG_SHB,
+};
+
+
+/// pcapng Simple Packet Block type.
+const PCAPNG_SPB: u32 = 0x0000_0003;
+
+/// Option codes.
+const OPT_ENDOFOPT: u16 = 0;
+const OPT_COMMENT: u16 = 1;
+const IF_NAME: u16 = 2;
+const IF_DESCRIPTION: u16 = 3;
+const IF_TSRESOL: u16 = 9;
+const IF_TSOFFSET: u16 = 14;
+const EPB_FLAGS: u16 = 2;
+
+/// Inbound/outbound bits of `epb_flags`.
+const EPB_DIRECTION_MASK: u32 = 0x3;
+const EPB_OUTBOUND: u32 = 0x2;
+
+/// Upper bound on the size of a block, to reject corrupted lengths
+/// before allocating.
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+
+/// Interface described by a pcapng Interface Description Block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PcapNgInterface {
+    /// Link-layer header type of the packets
+    pub linktype: u16,
+    /// Snapshot length, 0 if unlimited
+    pub snaplen: u32,
+    /// Interface name (`if_name`)
+    pub name: Option<String>,
+    /// Interface description (`if_description`)
+    pub description: Option<String>,
+    /// Timestamp resolution (`if_tsresol`): the raw option value,
+    /// i.e. a negative power of 10, or of 2 if the MSB is set
+    pub tsresol: u8,
+    /// Offset in seconds added to every timestamp (`if_tsoffset`)
+    pub tsoffset: i64,
+}
+
+impl PcapNgInterface {
+    /// Convert a timestamp, expressed in units of the
+    /// interface resolution, to the time since the Unix epoch.
+    ///
+    /// Fails if the offset moves the timestamp before the epoch,
+    /// or beyond the range of a [`Duration`].
+    fn timestamp(&self, units: u64) -> io::Result<Duration> {
+        let exp = u32::from(self.tsresol & 0x7f);
+        let (secs, nanos) = if self.tsresol & 0x80 == 0 {
+            // Checked by `tsresol` when the interface was read
+            let per_sec = 10u128.pow(exp);
+            let frac = units as u128 % per_sec;
+            (units as u128 / per_sec, frac * 1_000_000_000 / per_sec)
+        } else {
+            let frac = units as u128 & ((1u128 << exp) - 1);
+            ((units as u128) >> exp, (frac * 1_000_000_000) >> exp)
+        };
+        let secs = u64::try_from(secs)
+            .ok()
+            .and_then(|secs| secs.checked_add_signed(self.tsoffset))
+            .ok_or_else(|| {
+                invalid_data(format!(
+                    "pcapng timestamp out of range with offset {}s",
+                    self.tsoffset
+                ))
+            })?;
+        Ok(Duration::new(secs, nanos as u32))
+    }
+}
+
+impl Default for PcapNgInterface {
+    fn default() -> Self {
+        PcapNgInterface {
+            linktype: 0,
+            snaplen: 0,
+            name: None,
+            description: None,
+            // Microseconds, as mandated by the specification
+            tsresol: 6,
+            tsoffset: 0,
+        }
+    }
+}
+
+
+/// Backend which replays the packets of a pcapng file.
+///
+/// Every Interface Description Block of the current section is kept,
+/// and the interface ID of each packet becomes its
+/// [`PacketHeader::ifindex`], so that a single socket can replay a
+/// multi-interface trace. Timestamps follow the resolution and offset
+/// of their interface, and are relative to the Unix epoch. The first
+/// comment (`opt_comment`) of a packet is reported in
+/// [`PacketHeader::comment`], and any further comment is ignored.
+/// Packets flagged as outbound (`epb_flags`) have an
+/// [`Outgoing`](crate::Direction::Outgoing) [`PacketHeader::direction`].
+///
+/// Enhanced and Simple Packet Blocks are replayed, while other blocks
+/// are skipped. Packets longer than the snapshot length of their
+/// interface or than the ring frame size are truncated. A packet whose
+/// offset timestamp falls before the epoch is skipped with an error.
+///
+/// Once the whole file has been replayed, receives fail with
+/// [`RecvError::Closed`]. The backend cannot transmit.
+#[derive(Debug)]
+pub struct PcapNgBackend<R> {
+    reader: R,
+    big_endian: bool,
+    interfaces: Vec<PcapNgInterface>,
+}
+
+impl PcapNgBackend<BufReader<File>> {
+    /// Open the pcapng file at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapNgBackend<R> {
+    /// Read the first Section Header Block from `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut kind = [0; 4];
+        reader.read_exact(&mut kind)?;
+        if u32::from_le_bytes(kind) != PCAPNG_SHB {
+            return Err(invalid_data("not a pcapng file"));
+        }
+
+        let mut backend = PcapNgBackend {
+            reader,
+            big_endian: false,
+            interfaces: Vec::new(),
+        };
+        backend.read_section_header()?;
+        Ok(backend)
+    }
+
+    /// Interfaces described so far in the current section,
+    /// indexed by interface ID.
+    pub fn interfaces(&self) -> &[PcapNgInterface] {
+        &self.interfaces
+    }
+
+    fn u16_at(&self, buf: &[u8], offset: usize) -> u16 {
+        let bytes = buf[offset..offset + 2].try_into().unwrap();
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32_at(&self, buf: &[u8], offset: usize) -> u32 {
+        let bytes = buf[offset..offset + 4].try_into().unwrap();
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+
+    /// Read the rest of a Section Header Block, whose type has already
+    /// been read, and start a new section.
+    fn read_section_header(&mut self) -> io::Result<()> {
+        let mut fixed = [0; 8];
+        self.reader.read_exact(&mut fixed)?;
+        let magic = u32::from_le_bytes(fixed[4..8].try_into().unwrap());
+        self.big_endian = match magic {
+            PCAPNG_BYTE_ORDER_MAGIC => false,
+            _ if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+            _ => return Err(invalid_data("invalid pcapng byte-order magic")),
+        };
+        let len = self.block_len(self.u32_at(&fixed, 0))?;
+        if len < 28 {
+            return Err(invalid_data("pcapng section header too short"));
+        }
+
+        // Version, section length and options are not needed
+        skip(&mut self.reader, len - 12)?;
+        self.interfaces.clear();
+        Ok(())
+    }
+
+    fn block_len(&self, len: u32) -> io::Result<usize> {
+        let len = len as usize;
+        if len < 12 || !len.is_multiple_of(4) || len > MAX_BLOCK_SIZE {
+            return Err(invalid_data("invalid pcapng block length"));
+        }
+        Ok(len)
+    }
+
+    /// Parse the body of an Interface Description Block.
+    fn read_interface(&mut self, body: &[u8]) -> io::Result<()> {
+        if body.len() < 8 {
+            return Err(invalid_data("pcapng interface block too short"));
+        }
+        let mut interface = PcapNgInterface {
+            linktype: self.u16_at(body, 0),
+            snaplen: self.u32_at(body, 4),
+            ..PcapNgInterface::default()
+        };
+        for (code, value) in self.options(&body[8..]) {
+            match code {
+                IF_NAME => interface.name = Some(string(value)),
+                IF_DESCRIPTION => interface.description = Some(string(value)),
+                IF_TSRESOL if !value.is_empty() => {
+                    interface.tsresol = tsresol(value[0])?;
+                }
+                IF_TSOFFSET if value.len() == 8 => {
+                    let bytes = value.try_into().unwrap();
+                    interface.tsoffset = if self.big_endian {
+                        i64::from_be_bytes(bytes)
+                    } else {
+                        i64::from_le_bytes(bytes)
+                    };
+                }
+                _ => {}
+            }
+        }
+        self.interfaces.push(interface);
+        Ok(())
+    }
+
+    /// Iterate over the options in `buf`, as (code, value).
+    fn options<'b>(
+        &self,
+        mut buf: &'b [u8],
+    ) -> impl Iterator<Item = (u16, &'b [u8])> + 'b {
+        let big_endian = self.big_endian;
+        std::iter::from_fn(move || {
+            if buf.len() < 4 {
+                return None;
+            }
+            let (code, len) = if big_endian {
+                (
+                    u16::from_be_bytes([buf[0], buf[1]]),
+                    u16::from_be_bytes([buf[2], buf[3]]),
+                )
+            } else {
+                (
+                    u16::from_le_bytes([buf[0], buf[1]]),
+                    u16::from_le_bytes([buf[2], buf[3]]),
+                )
+            };
+            let len = len as usize;
+            if code == OPT_ENDOFOPT || buf.len() < 4 + len {
+                return None;
+            }
+            let value = &buf[4..4 + len];
+            buf = &buf[(4 + padded(len)).min(buf.len())..];
+            Some((code, value))
+        })
+    }
+
+    fn interface(&self, id: u32) -> io::Result<&PcapNgInterface> {
+        self.interfaces.get(id as usize).ok_or_else(|| {
+            invalid_data(format!("undeclared pcapng interface {id}"))
+        })
+    }
+
+    /// Read the packet data of a block straight into `frame`,
+    /// skipping the bytes which do not fit and the padding.
+    ///
+    /// Returns the number of bytes stored in `frame`.
+    fn read_data(
+        &mut self,
+        frame: &mut [u8],
+        caplen: usize,
+        snaplen: u32,
+    ) -> io::Result<usize> {
+        let mut stored = caplen.min(frame.len());
+        if snaplen != 0 {
+            stored = stored.min(snaplen as usize);
+        }
+        self.reader.read_exact(&mut frame[..stored])?;
+        skip(&mut self.reader, padded(caplen) - stored)?;
+        Ok(stored)
+    }
+
+    /// Parse an Enhanced Packet Block whose body is `len` bytes long.
+    fn read_enhanced_packet(
+        &mut self,
+        len: usize,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        let mut fixed = [0; 20];
+        if len < fixed.len() {
+            return Err(invalid_data("pcapng packet block too short").into());
+        }
+        self.reader.read_exact(&mut fixed)?;
+        let id = self.u32_at(&fixed, 0);
+        let units = (self.u32_at(&fixed, 4) as u64) << 32
+            | self.u32_at(&fixed, 8) as u64;
+        let caplen = self.u32_at(&fixed, 12) as usize;
+        let orig_len = self.u32_at(&fixed, 16) as usize;
+        if fixed.len() + padded(caplen) > len {
+            return Err(
+                invalid_data("pcapng packet data exceeds its block").into()
+            );
+        }
+
+        let snaplen = self.interface(id)?.snaplen;
+        header.caplen = self.read_data(frame, caplen, snaplen)?;
+        header.len = orig_len.max(caplen);
+        header.ifindex = id;
+
+        let mut options = vec![0; len - fixed.len() - padded(caplen)];
+        self.reader.read_exact(&mut options)?;
+        for (code, value) in self.options(&options) {
+            match code {
+                // Only the first comment fits the header
+                OPT_COMMENT if header.comment.is_none() => {
+                    header.comment = Some(string(value));
+                }
+                EPB_FLAGS if value.len() == 4 => {
+                    let flags = self.u32_at(value, 0);
+                    if flags & EPB_DIRECTION_MASK == EPB_OUTBOUND {
+                        header.direction = Direction::Outgoing;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Only once the whole block is read, so that the next
+        // packet can still be replayed
+        header.timestamp = self.interface(id)?.timestamp(units)?;
+        Ok(())
+    }
+
+    /// Parse a Simple Packet Block whose body is `len` bytes long.
+    fn read_simple_packet(
+        &mut self,
+        len: usize,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        let mut fixed = [0; 4];
+        if len < fixed.len() {
+            return Err(invalid_data("pcapng packet block too short").into());
+        }
+        self.reader.read_exact(&mut fixed)?;
+        let orig_len = self.u32_at(&fixed, 0) as usize;
+
+        // Simple packets belong to the first interface, and their
+        // stored length is implied by the block length
+        let snaplen = self.interface(0)?.snaplen;
+        let caplen = (len - fixed.len()).min(orig_len);
+        header.caplen = self.read_data(frame, caplen, snaplen)?;
+        header.len = orig_len;
+        skip(&mut self.reader, len - fixed.len() - padded(caplen))?;
+        Ok(())
+    }
+}
+
+impl<R: Read> Backend for PcapNgBackend<R> {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        loop {
+            let mut kind = [0; 4];
+            if !read_exact_or_eof(&mut self.reader, &mut kind)? {
+                return Err(RecvError::Closed);
+            }
+            if u32::from_le_bytes(kind) == PCAPNG_SHB {
+                self.read_section_header()?;
+                continue;
+            }
+
+            let kind = self.u32_at(&kind, 0);
+            let mut len = [0; 4];
+            self.reader.read_exact(&mut len)?;
+            let len = self.block_len(self.u32_at(&len, 0))?;
+            // Length of the block body, without type, length and trailer
+            let body = len - 12;
+
+            let block = match kind {
+                PCAPNG_IDB => {
+                    let mut buf = vec![0; body];
+                    self.reader.read_exact(&mut buf)?;
+                    self.read_interface(&buf).map_err(RecvError::from)
+                }
+                PCAPNG_EPB => self.read_enhanced_packet(body, frame, header),
+                PCAPNG_SPB => self.read_simple_packet(body, frame, header),
+                _ => skip(&mut self.reader, body).map_err(RecvError::from),
+            };
+            // Trailing block length, also after an invalid block
+            // whose body was read
+            skip(&mut self.reader, 4)?;
+            block?;
+
+            if matches!(kind, PCAPNG_EPB | PCAPNG_SPB) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> io::Result<()> {
+        Err(io::Error::new(
+            ErrorKind::Unsupported,
+            "pcapng replay sockets cannot transmit",
+        ))
+    }
+}
+
+
+fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg.into())
+}
+
+/// Validate an `if_tsresol` value, whose units per second
+/// must fit the timestamp conversion.
+fn tsresol(value: u8) -> io::Result<u8> {
+    let exp = u32::from(value & 0x7f);
+    if value & 0x80 == 0 && 10u128.checked_pow(exp).is_none() {
+        return Err(invalid_data(format!(
+            "unsupported pcapng timestamp resolution 10^-{exp}"
+        )));
+    }
+    Ok(value)
+}
+
+/// Option value as a string, without the trailing NULs
+/// written by some tools.
+fn string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches('\0')
+        .to_owned()
+}
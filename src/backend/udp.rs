@@ -0,0 +1,82 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
+
+use crate::backend::{Backend, DatagramBuf};
+use crate::error::RecvError;
+use crate::options::SocketOptions;
+use crate::packet::PacketHeader;
+
+
+/// Backend which carries raw frames as the payload of UDP datagrams
+/// over a connected [`UdpSocket`], usually on the loopback interface.
+///
+/// Every datagram carries exactly one frame, so that the backend can
+/// talk to harnesses which already emit UDP-encapsulated frames.
+/// Batching is left to the socket rings: a flush sends one datagram
+/// per queued frame. Datagrams larger than the ring frame size are
+/// truncated, with a `caplen` smaller than their `len`. Received
+/// packets are timestamped with the wall-clock time, since the Unix
+/// epoch.
+///
+/// The socket is switched to nonblocking mode when the backend is
+/// opened. Frames which the kernel refuses to send are counted as
+/// [`Stats::tx_dropped`](crate::Stats::tx_dropped).
+#[derive(Debug)]
+pub struct UdpBackend {
+    socket: UdpSocket,
+    buf: DatagramBuf,
+}
+
+impl UdpBackend {
+    /// Create a backend over `socket`, which must be connected.
+    pub fn new(socket: UdpSocket) -> Self {
+        UdpBackend {
+            socket,
+            buf: DatagramBuf::new(),
+        }
+    }
+
+    /// Bind a socket to `127.0.0.1:local_port`
+    /// and connect it to `127.0.0.1:peer_port`.
+    ///
+    /// `local_port` can be zero to let the system pick a free port,
+    /// which can then be read from [`Self::local_addr`].
+    pub fn loopback(local_port: u16, peer_port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, local_port))?;
+        socket.connect((Ipv4Addr::LOCALHOST, peer_port))?;
+        Ok(Self::new(socket))
+    }
+
+    /// Address the socket is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Address the socket is connected to.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.peer_addr()
+    }
+
+    /// Underlying socket.
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+}
+
+impl Backend for UdpBackend {
+    fn open(&mut self, _opts: &SocketOptions) -> io::Result<()> {
+        self.socket.set_nonblocking(true)
+    }
+
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        self.buf.recv(frame, header, |buf| self.socket.recv(buf))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.socket.send(frame).map(|_| ())
+    }
+}
@@ -0,0 +1,82 @@
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+
+use crate::backend::{Backend, DatagramBuf};
+use crate::error::RecvError;
+use crate::options::SocketOptions;
+use crate::packet::PacketHeader;
+
+
+/// Backend which exchanges frames as datagrams over a connected
+/// [`UnixDatagram`] socket, so that sockets in different processes
+/// can talk to each other without privileges.
+///
+/// Every datagram carries exactly one frame. Datagrams larger than
+/// the ring frame size are truncated, with a `caplen` smaller than
+/// their `len`, which is the size of the datagram up to
+/// [`MAX_FRAME_SIZE`](crate::options::MAX_FRAME_SIZE) + 1. Received
+/// packets are timestamped with the wall-clock time, since the Unix
+/// epoch.
+///
+/// The socket is switched to nonblocking mode when the backend is
+/// opened. Frames sent while the peer is missing or its buffer is full
+/// are counted as [`Stats::tx_dropped`](crate::Stats::tx_dropped).
+#[derive(Debug)]
+pub struct UnixDatagramBackend {
+    socket: UnixDatagram,
+    buf: DatagramBuf,
+}
+
+impl UnixDatagramBackend {
+    /// Create a backend over `socket`, which must be connected.
+    pub fn new(socket: UnixDatagram) -> Self {
+        UnixDatagramBackend {
+            socket,
+            buf: DatagramBuf::new(),
+        }
+    }
+
+    /// Bind a socket to `local` and connect it to `peer`.
+    ///
+    /// The peer must already be bound: the process which starts last
+    /// should connect, or both can bind first and then call
+    /// [`UnixDatagram::connect`] through [`Self::socket`].
+    pub fn bind(
+        local: impl AsRef<Path>,
+        peer: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let socket = UnixDatagram::bind(local)?;
+        socket.connect(peer)?;
+        Ok(Self::new(socket))
+    }
+
+    /// Create two backends connected to each other.
+    pub fn pair() -> io::Result<(Self, Self)> {
+        let (a, b) = UnixDatagram::pair()?;
+        Ok((Self::new(a), Self::new(b)))
+    }
+
+    /// Underlying socket.
+    pub fn socket(&self) -> &UnixDatagram {
+        &self.socket
+    }
+}
+
+impl Backend for UnixDatagramBackend {
+    fn open(&mut self, _opts: &SocketOptions) -> io::Result<()> {
+        self.socket.set_nonblocking(true)
+    }
+
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        self.buf.recv(frame, header, |buf| self.socket.recv(buf))
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.socket.send(frame).map(|_| ())
+    }
+}
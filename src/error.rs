@@ -0,0 +1,201 @@
+//! Error types.
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::io;
+
+use crate::packet::PacketId;
+use crate::status::SlotState;
+
+
+/// Error returned when building invalid
+/// [`SocketOptions`](crate::SocketOptions).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionsError {
+    /// The ring must have at least one slot
+    ZeroSlots,
+    /// The requested number of slots exceeds the supported maximum
+    TooManySlots { requested: usize, max: usize },
+    /// Frames must be able to hold at least one byte
+    ZeroFrameSize,
+    /// The requested frame size exceeds the supported maximum
+    FrameTooLarge { requested: usize, max: usize },
+    /// The frame alignment must be a power of two
+    /// not exceeding the supported maximum
+    BadFrameAlign { requested: usize, max: usize },
+}
+
+impl Display for OptionsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptionsError::ZeroSlots => {
+                write!(f, "the ring must have at least one slot")
+            }
+            OptionsError::TooManySlots { requested, max } => write!(
+                f,
+                "requested {requested} slots, but at most {max} are supported"
+            ),
+            OptionsError::ZeroFrameSize => {
+                write!(f, "the frame size must be greater than zero")
+            }
+            OptionsError::FrameTooLarge { requested, max } => write!(
+                f,
+                "requested {requested}-byte frames, but at most {max} bytes \
+                 are supported"
+            ),
+            OptionsError::BadFrameAlign { requested, max } => write!(
+                f,
+                "the frame alignment must be a power of two up to {max}, \
+                 but {requested} was requested"
+            ),
+        }
+    }
+}
+
+impl Error for OptionsError {}
+
+
+/// Error returned by [`Socket::recv`](crate::Socket::recv)
+/// and [`Socket::try_recv`](crate::Socket::try_recv).
+#[derive(Debug)]
+pub enum RecvError {
+    /// No packet can be received right now, either because the next
+    /// slot is still held by a packet (i.e. the ring is full, which is
+    /// counted in [`Stats::rx_ring_full`](crate::Stats::rx_ring_full))
+    /// or because the backend has no packet available yet: retry later
+    WouldBlock,
+    /// The socket has been closed, or its packet source is exhausted
+    Closed,
+    /// The packet did not fit into a slot, so it has been dropped
+    Truncated { len: usize, max: usize },
+    /// The packet source failed
+    Backend(io::Error),
+}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::WouldBlock => {
+                write!(
+                    f,
+                    "no packet available yet, or the next slot of the RX \
+                     ring is still in use"
+                )
+            }
+            RecvError::Closed => write!(f, "the socket has been closed"),
+            RecvError::Truncated { len, max } => write!(
+                f,
+                "{len}-byte packet does not fit into a {max}-byte slot"
+            ),
+            RecvError::Backend(err) => write!(f, "backend error: {err}"),
+        }
+    }
+}
+
+impl Error for RecvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RecvError::Backend(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RecvError {
+    fn from(err: io::Error) -> Self {
+        RecvError::Backend(err)
+    }
+}
+
+
+/// Error returned by [`Socket::send`](crate::Socket::send).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendError {
+    /// The next TX slot has not been completed yet
+    RingFull,
+    /// The frame does not fit into a TX slot
+    FrameTooLarge { len: usize, max: usize },
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::RingFull => write!(f, "the TX ring is full"),
+            SendError::FrameTooLarge { len, max } => write!(
+                f,
+                "{len}-byte frame does not fit into a {max}-byte TX slot"
+            ),
+        }
+    }
+}
+
+impl Error for SendError {}
+
+
+/// Error returned by [`Socket::release`](crate::Socket::release)
+/// and [`Socket::lookup`](crate::Socket::lookup).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReleaseError {
+    /// The id does not refer to a slot of the ring
+    UnknownId(PacketId),
+    /// The packet has already been released
+    DoubleRelease(PacketId),
+    /// The packet is still held by its [`RecvPacket`](crate::RecvPacket),
+    /// which releases it when dropped
+    HandleAlive(PacketId),
+    /// The slot has been reused for another packet
+    /// since the id was handed out
+    Stale(PacketId),
+}
+
+impl Display for ReleaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReleaseError::UnknownId(id) => {
+                write!(f, "packet {id} does not belong to the ring")
+            }
+            ReleaseError::DoubleRelease(id) => {
+                write!(f, "packet {id} has already been released")
+            }
+            ReleaseError::HandleAlive(id) => {
+                write!(f, "packet {id} is still held by its handle")
+            }
+            ReleaseError::Stale(id) => write!(f, "packet {id} is stale"),
+        }
+    }
+}
+
+impl Error for ReleaseError {}
+
+
+/// Error returned by [`SlotStatus::transition`](crate::SlotStatus::transition)
+/// when a slot cannot move to the requested state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IllegalTransition {
+    /// State the slot was expected to be in
+    pub expected: SlotState,
+    /// State the slot was actually in
+    pub actual: SlotState,
+    /// Requested state
+    pub to: SlotState,
+}
+
+impl Display for IllegalTransition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.expected.can_transition_to(self.to) {
+            write!(
+                f,
+                "cannot move slot from {} to {}: slot is {}",
+                self.expected, self.to, self.actual
+            )
+        } else {
+            write!(
+                f,
+                "slot transition from {} to {} is not allowed",
+                self.expected, self.to
+            )
+        }
+    }
+}
+
+impl Error for IllegalTransition {}
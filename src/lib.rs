@@ -0,0 +1,87 @@
+//! Prototype of RustNethuns' socket model, meant to be run with the
+//! [Miri](https://github.com/rust-lang/miri) interpreter in order to assess
+//! the absence of Undefined Behavior in the packet reception mechanism.
+//!
+//! The crate exposes a [`Socket`] wrapping a single RX [`Ring`] of
+//! [`RingSlot`]s, whose frames live in one [`FrameArena`] and are filled
+//! by a [`Backend`]:
+//!
+//! - [`MemoryBackend`], the default, which generates synthetic packets;
+//! - [`PcapBackend`] and [`PcapNgBackend`], which replay a capture file;
+//! - [`LinkBackend`], an end of a [`VirtualLink`] which connects two
+//!   sockets in the same process;
+//! - [`UnixDatagramBackend`] (Unix only) and [`UdpBackend`], which exchange
+//!   frames over a datagram socket.
+//!
+//! Every received packet is returned as a [`RecvPacket`], which borrows
+//! the slot frame and its [`PacketHeader`], and releases the slot when
+//! dropped. Packets can also be received in bursts through a
+//! [`PacketBatch`], or detached into a [`PacketId`] and released later
+//! through [`Socket::release`].
+//! Frames are transmitted through a [`TxRing`], whose slots are filled by
+//! [`Socket::send`], transmitted by [`Socket::flush`] and reclaimed by
+//! [`Socket::complete_tx`]. Frames can also be built in place through a
+//! [`TxSlot`] reserved by [`Socket::reserve_tx`].
+//! A [`Socket`] is `!Sync`: a [`SharedSocket`] can be used instead to
+//! receive from several threads.
+//! Received packets can be saved to capture files through a
+//! [`PcapWriter`], or a [`RotatingPcapWriter`] which splits the capture
+//! across several files.
+//! Every slot follows the lifecycle described by [`SlotState`].
+//! An [`XdpSocket`] models the AF_XDP ownership of frames instead,
+//! which cycle through a UMEM and its four rings, and a [`NetmapPort`]
+//! models netmap rings, whose slots can swap buffers. A [`ShmSocket`]
+//! (Unix only) receives in place from a [`ShmRing`] shared with another
+//! process, and its packets release the shared slots.
+//! The ring geometry is configured through [`SocketOptions`].
+//!
+//! ```
+//! use rust_nethuns_miri::Socket;
+//!
+//! let socket = Socket::new();
+//! let packet = socket.recv().unwrap();
+//! assert_eq!(packet.idx(), 0);
+//! drop(packet);
+//! assert_eq!(socket.stats().rx_packets, 1);
+//! ```
+
+pub mod arena;
+pub mod backend;
+pub mod error;
+pub mod netmap;
+pub mod options;
+pub mod packet;
+pub mod ring;
+pub mod shared;
+#[cfg(unix)]
+pub mod shm;
+pub mod socket;
+pub mod stats;
+pub mod status;
+pub mod writer;
+pub mod xdp;
+
+pub use arena::FrameArena;
+pub use backend::{
+    Backend, Impairments, LinkBackend, MemoryBackend, PcapBackend,
+    PcapNgBackend, UdpBackend, VirtualLink,
+};
+#[cfg(unix)]
+pub use backend::UnixDatagramBackend;
+pub use error::{
+    IllegalTransition, OptionsError, RecvError, ReleaseError, SendError,
+};
+pub use netmap::{NetmapPort, NetmapRing, NetmapSlot};
+pub use options::{CaptureDir, SocketOptions, SocketOptionsBuilder};
+pub use packet::{
+    Direction, PacketBatch, PacketHeader, PacketId, RecvPacket, TxSlot,
+};
+pub use ring::{Ring, RingSlot, TxRing, TxRingSlot};
+pub use shared::SharedSocket;
+#[cfg(unix)]
+pub use shm::{ShmProducer, ShmRing, ShmSocket};
+pub use socket::Socket;
+pub use stats::Stats;
+pub use status::{SlotState, SlotStatus};
+pub use writer::{PcapFormat, PcapWriter, RotatingPcapWriter, Rotation};
+pub use xdp::{XdpFrame, XdpSocket};
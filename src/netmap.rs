@@ -0,0 +1,426 @@
+//! netmap-style ports, whose rings are shared with the kernel
+//! through `head`, `cur` and `tail` pointers.
+
+use std::io;
+
+use crate::arena::FrameArena;
+use crate::backend::{Backend, MemoryBackend};
+use crate::error::RecvError;
+use crate::options::SocketOptions;
+use crate::packet::PacketHeader;
+use crate::stats::Stats;
+
+
+/// Slot flag set when the buffer index of a slot
+/// has been changed by the user (`NS_BUF_CHANGED`).
+pub const NS_BUF_CHANGED: u16 = 0x0001;
+
+
+/// Slot of a [`NetmapRing`], which points to a buffer of the port.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetmapSlot {
+    buf_idx: u32,
+    len: usize,
+    flags: u16,
+}
+
+impl NetmapSlot {
+    /// Index of the buffer in the port buffer pool.
+    pub fn buf_idx(&self) -> u32 {
+        self.buf_idx
+    }
+
+    /// Length of the packet stored in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the packet is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Slot flags, such as [`NS_BUF_CHANGED`].
+    pub fn flags(&self) -> u16 {
+        self.flags
+    }
+}
+
+
+/// Structure which emulates a netmap ring.
+///
+/// The slots in `[head, tail)` belong to the user, the others to the
+/// kernel. The user returns slots to the kernel by moving `head`
+/// forward (`cur` marks how far the user has looked, and is never
+/// behind `head`), and the kernel hands slots to the user by moving
+/// `tail` forward during a sync. One slot is always left between
+/// `tail` and `head`, so that a full ring can be told from an empty one.
+///
+/// On an RX ring the user slots hold received packets. On a TX ring
+/// they are free to be filled with packets to transmit.
+#[derive(Debug)]
+pub struct NetmapRing {
+    slots: Vec<NetmapSlot>,
+    head: usize,
+    cur: usize,
+    tail: usize,
+    /// Next slot to be transmitted by the kernel (TX ring only)
+    hwcur: usize,
+}
+
+impl NetmapRing {
+    /// Create a ring whose slots point to the buffers
+    /// `first_buf..first_buf + num_slots`, with `tail` slots
+    /// belonging to the user.
+    fn new(num_slots: usize, first_buf: usize, tail: usize) -> Self {
+        NetmapRing {
+            slots: (0..num_slots)
+                .map(|i| NetmapSlot {
+                    buf_idx: (first_buf + i) as u32,
+                    ..NetmapSlot::default()
+                })
+                .collect(),
+            head: 0,
+            cur: 0,
+            tail,
+            hwcur: 0,
+        }
+    }
+
+    /// Number of slots.
+    pub fn num_slots(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// First slot belonging to the user.
+    pub fn head(&self) -> usize {
+        self.head
+    }
+
+    /// Current position of the user, between `head` and `tail`.
+    pub fn cur(&self) -> usize {
+        self.cur
+    }
+
+    /// First slot belonging to the kernel.
+    pub fn tail(&self) -> usize {
+        self.tail
+    }
+
+    /// Slot `idx`.
+    pub fn slot(&self, idx: usize) -> &NetmapSlot {
+        &self.slots[idx]
+    }
+
+    /// Slot following `idx` (`nm_ring_next`).
+    pub fn next(&self, idx: usize) -> usize {
+        (idx + 1) % self.slots.len()
+    }
+
+    /// Number of slots between `cur` and `tail` (`nm_ring_space`).
+    pub fn space(&self) -> usize {
+        self.distance(self.cur, self.tail)
+    }
+
+    /// Check whether no slot is left between `cur` and `tail`
+    /// (`nm_ring_empty`).
+    pub fn is_empty(&self) -> bool {
+        self.cur == self.tail
+    }
+
+    /// Return the `n` slots starting at `head` to the kernel,
+    /// moving `cur` along if it falls behind.
+    ///
+    /// # Panics
+    ///
+    /// Panics if fewer than `n` slots belong to the user.
+    pub fn advance(&mut self, n: usize) {
+        let user = self.distance(self.head, self.tail);
+        assert!(n <= user, "only {user} slots belong to the user");
+        let done = self.distance(self.head, self.cur);
+        self.head = (self.head + n) % self.slots.len();
+        if n > done {
+            self.cur = self.head;
+        }
+    }
+
+    /// Set `cur`, which must lie between `head` and `tail` (included).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cur` is out of the user slots.
+    pub fn set_cur(&mut self, cur: usize) {
+        assert!(
+            self.distance(self.head, cur)
+                <= self.distance(self.head, self.tail),
+            "cur must lie between head and tail"
+        );
+        self.cur = cur;
+    }
+
+    /// Number of slots from `from` to `to`, moving forward.
+    fn distance(&self, from: usize, to: usize) -> usize {
+        (to + self.slots.len() - from) % self.slots.len()
+    }
+
+    /// Check whether the slot `idx` belongs to the user.
+    fn is_user(&self, idx: usize) -> bool {
+        idx < self.slots.len()
+            && self.distance(self.head, idx)
+                < self.distance(self.head, self.tail)
+    }
+
+    /// Clear the [`NS_BUF_CHANGED`] flags, as the kernel does
+    /// when it reloads the buffer addresses during a sync.
+    fn reload_buffers(&mut self, num_bufs: usize) {
+        for slot in &mut self.slots {
+            if slot.flags & NS_BUF_CHANGED != 0 {
+                assert!((slot.buf_idx as usize) < num_bufs);
+                slot.flags &= !NS_BUF_CHANGED;
+            }
+        }
+    }
+}
+
+
+/// Structure which emulates a netmap port with one RX ring
+/// and one TX ring.
+///
+/// Both rings point into a single pool of buffers, so that a received
+/// packet can be forwarded without copies by swapping the buffer
+/// indices of an RX slot and a TX slot ([`NetmapPort::swap`]).
+/// The kernel side of the rings is played by a [`Backend`], which fills
+/// RX buffers during [`NetmapPort::rxsync`] and transmits TX buffers
+/// during [`NetmapPort::txsync`].
+///
+/// Like a netmap file descriptor, a port is meant to be used by one
+/// thread at a time: every operation on the rings borrows the port.
+///
+/// ```
+/// use rust_nethuns_miri::NetmapPort;
+///
+/// let mut port = NetmapPort::new();
+/// port.rxsync().unwrap();
+/// // Forward every received packet without copying it
+/// while !port.rx().is_empty() && !port.tx().is_empty() {
+///     port.swap(port.rx().cur(), port.tx().cur());
+///     port.rx_mut().advance(1);
+///     port.tx_mut().advance(1);
+/// }
+/// assert_eq!(port.txsync(), 4);
+/// ```
+#[derive(Debug)]
+pub struct NetmapPort<B: Backend = MemoryBackend> {
+    bufs: FrameArena,
+    rx: NetmapRing,
+    tx: NetmapRing,
+    backend: B,
+    stats: Stats,
+}
+
+impl NetmapPort {
+    /// Create a new synthetic port with the default options.
+    pub fn new() -> Self {
+        Self::new_with(SocketOptions::default())
+    }
+
+    /// Create a new synthetic port whose rings
+    /// are described by `opts`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rings have less than two slots.
+    pub fn new_with(opts: SocketOptions) -> Self {
+        Self::with_backend(opts, MemoryBackend::new())
+            .expect("the rings have less than two slots")
+    }
+}
+
+impl<B: Backend> NetmapPort<B> {
+    /// Create a new port over `backend`, with rings of `opts.num_slots()`
+    /// slots and a pool of buffers of `opts.frame_size()` bytes, one for
+    /// each slot of the two rings.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if the rings have less
+    /// than two slots, as one slot of each ring is left to the kernel.
+    pub fn with_backend(
+        opts: SocketOptions,
+        mut backend: B,
+    ) -> io::Result<Self> {
+        let num_slots = opts.num_slots();
+        if num_slots < 2 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("netmap rings need at least 2 slots, not {num_slots}"),
+            ));
+        }
+        backend.open(&opts)?;
+        Ok(NetmapPort {
+            bufs: FrameArena::new(
+                2 * num_slots,
+                opts.frame_size(),
+                opts.frame_align(),
+            ),
+            // The RX ring starts empty, the TX ring
+            // with every slot but one available
+            rx: NetmapRing::new(num_slots, 0, 0),
+            tx: NetmapRing::new(num_slots, num_slots, num_slots - 1),
+            backend,
+            stats: Stats::default(),
+        })
+    }
+
+    /// RX ring.
+    pub fn rx(&self) -> &NetmapRing {
+        &self.rx
+    }
+
+    /// RX ring, to move its `head` and `cur`.
+    pub fn rx_mut(&mut self) -> &mut NetmapRing {
+        &mut self.rx
+    }
+
+    /// TX ring.
+    pub fn tx(&self) -> &NetmapRing {
+        &self.tx
+    }
+
+    /// TX ring, to move its `head` and `cur`.
+    pub fn tx_mut(&mut self) -> &mut NetmapRing {
+        &mut self.tx
+    }
+
+    /// Packet received into the RX slot `idx`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slot does not belong to the user.
+    pub fn rx_packet(&self, idx: usize) -> &[u8] {
+        assert!(self.rx.is_user(idx), "RX slot {idx} belongs to the kernel");
+        let slot = self.rx.slots[idx];
+        // SAFETY: the buffer is only mutated through `&mut self`.
+        let buf = unsafe { self.bufs.frame(slot.buf_idx as usize) };
+        &buf[..slot.len]
+    }
+
+    /// Fill the TX slot `idx` with the first `len` bytes of its buffer,
+    /// which `build` can write to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the slot does not belong to the user,
+    /// or if `len` exceeds the buffer size.
+    pub fn tx_fill(
+        &mut self,
+        idx: usize,
+        len: usize,
+        build: impl FnOnce(&mut [u8]),
+    ) {
+        assert!(self.tx.is_user(idx), "TX slot {idx} belongs to the kernel");
+        let slot = &mut self.tx.slots[idx];
+        // SAFETY: the port is mutably borrowed, so no other
+        // borrow of the buffer is alive.
+        let buf = unsafe { self.bufs.frame_mut(slot.buf_idx as usize) };
+        build(&mut buf[..len]);
+        slot.len = len;
+    }
+
+    /// Swap the buffers of the RX slot `rx_idx` and of the TX slot
+    /// `tx_idx`, so that the received packet is queued for transmission
+    /// without being copied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either slot does not belong to the user.
+    pub fn swap(&mut self, rx_idx: usize, tx_idx: usize) {
+        assert!(
+            self.rx.is_user(rx_idx),
+            "RX slot {rx_idx} belongs to the kernel"
+        );
+        assert!(
+            self.tx.is_user(tx_idx),
+            "TX slot {tx_idx} belongs to the kernel"
+        );
+        let rx = &mut self.rx.slots[rx_idx];
+        let tx = &mut self.tx.slots[tx_idx];
+        std::mem::swap(&mut rx.buf_idx, &mut tx.buf_idx);
+        tx.len = rx.len;
+        rx.flags |= NS_BUF_CHANGED;
+        tx.flags |= NS_BUF_CHANGED;
+    }
+
+    /// Give the RX slots released by the user back to the kernel, and
+    /// fill the kernel slots with new packets, moving `tail` forward.
+    ///
+    /// Returns the number of received packets. Fails with the error
+    /// returned by [`Backend::fill`] if no packet was received and the
+    /// ring had room for one.
+    pub fn rxsync(&mut self) -> Result<usize, RecvError> {
+        self.rx.reload_buffers(self.bufs.len());
+
+        let mut count = 0;
+        while self.rx.next(self.rx.tail) != self.rx.head {
+            let slot = &mut self.rx.slots[self.rx.tail];
+            // SAFETY: the port is mutably borrowed, so no other
+            // borrow of the buffer is alive.
+            let buf = unsafe { self.bufs.frame_mut(slot.buf_idx as usize) };
+            let mut header = PacketHeader::default();
+            match self.backend.fill(buf, &mut header) {
+                Ok(()) => {}
+                Err(err) if count == 0 => return Err(err),
+                Err(_) => break,
+            }
+            slot.len = header.caplen.min(buf.len());
+            self.rx.tail = self.rx.next(self.rx.tail);
+            count += 1;
+        }
+        if count == 0 {
+            self.stats.rx_ring_full += 1;
+        }
+        self.stats.rx_packets += count as u64;
+        Ok(count)
+    }
+
+    /// Transmit the TX slots released by the user, i.e. the ones up to
+    /// `head`, and hand them back to the user by moving `tail` forward.
+    ///
+    /// Packets which the backend fails to transmit are dropped.
+    /// Returns the number of flushed packets.
+    pub fn txsync(&mut self) -> usize {
+        self.tx.reload_buffers(self.bufs.len());
+
+        let mut count = 0;
+        while self.tx.hwcur != self.tx.head {
+            let slot = self.tx.slots[self.tx.hwcur];
+            // SAFETY: see `NetmapPort::rx_packet`.
+            let buf = unsafe { self.bufs.frame(slot.buf_idx as usize) };
+            match self.backend.transmit(&buf[..slot.len]) {
+                Ok(()) => self.stats.tx_packets += 1,
+                Err(_) => self.stats.tx_dropped += 1,
+            }
+            self.tx.hwcur = self.tx.next(self.tx.hwcur);
+            count += 1;
+        }
+        // Every transmitted slot is available again
+        self.tx.tail =
+            (self.tx.head + self.tx.num_slots() - 1) % self.tx.num_slots();
+        count
+    }
+
+    /// Port statistics, collected by the rings and the backend.
+    pub fn stats(&self) -> Stats {
+        self.stats + self.backend.stats()
+    }
+}
+
+impl<B: Backend> Drop for NetmapPort<B> {
+    fn drop(&mut self) {
+        self.backend.close();
+    }
+}
+
+impl Default for NetmapPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
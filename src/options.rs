@@ -0,0 +1,225 @@
+//! Socket options, modeled on Nethuns' `nethuns_socket_options`.
+
+use std::time::Duration;
+
+use crate::error::OptionsError;
+use crate::packet::Direction;
+
+
+/// Maximum number of slots of a ring.
+pub const MAX_SLOTS: usize = 65536;
+
+/// Maximum size in bytes of a ring frame.
+pub const MAX_FRAME_SIZE: usize = 65536;
+
+/// Default alignment of ring frames (`TPACKET_ALIGNMENT`).
+pub const DEFAULT_FRAME_ALIGN: usize = 16;
+
+/// Maximum alignment of ring frames (the size of a page).
+pub const MAX_FRAME_ALIGN: usize = 4096;
+
+
+/// Capture direction of a socket (`nethuns_capture_dir`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaptureDir {
+    /// Capture incoming packets only
+    In,
+    /// Capture outgoing packets only
+    Out,
+    /// Capture both incoming and outgoing packets
+    #[default]
+    InOut,
+}
+
+impl CaptureDir {
+    /// Check whether packets going in `direction` are captured.
+    pub fn captures(self, direction: Direction) -> bool {
+        match self {
+            CaptureDir::In => direction == Direction::Incoming,
+            CaptureDir::Out => direction == Direction::Outgoing,
+            CaptureDir::InOut => true,
+        }
+    }
+}
+
+
+/// Options used to create a [`Socket`](crate::Socket).
+///
+/// Options can only be obtained through [`SocketOptions::builder`]
+/// or [`Default`], so they are always valid.
+/// The default geometry (5 slots of 5 bytes each) is the one of the
+/// original prototype, which keeps Miri runs short. Frames are aligned
+/// to [`DEFAULT_FRAME_ALIGN`] bytes by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketOptions {
+    num_slots: usize,
+    frame_size: usize,
+    frame_align: usize,
+    timeout: Duration,
+    dir: CaptureDir,
+    promisc: bool,
+    rxhash: bool,
+}
+
+impl SocketOptions {
+    /// Create a builder initialized with the default options.
+    pub fn builder() -> SocketOptionsBuilder {
+        SocketOptionsBuilder {
+            opts: SocketOptions::default(),
+        }
+    }
+
+    /// Number of slots of the ring.
+    pub fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    /// Size in bytes of each slot frame.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Alignment in bytes of each slot frame.
+    pub fn frame_align(&self) -> usize {
+        self.frame_align
+    }
+
+    /// Receive timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Capture direction.
+    pub fn dir(&self) -> CaptureDir {
+        self.dir
+    }
+
+    /// Whether the interface is put in promiscuous mode.
+    ///
+    /// The option is advisory: none of the built-in backends
+    /// filter packets by destination, so it has no effect on them.
+    pub fn promisc(&self) -> bool {
+        self.promisc
+    }
+
+    /// Whether the RSS hash is computed for received packets.
+    pub fn rxhash(&self) -> bool {
+        self.rxhash
+    }
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            num_slots: 5,
+            frame_size: 5,
+            frame_align: DEFAULT_FRAME_ALIGN,
+            timeout: Duration::ZERO,
+            dir: CaptureDir::default(),
+            promisc: false,
+            rxhash: false,
+        }
+    }
+}
+
+
+/// Builder for [`SocketOptions`].
+///
+/// ```
+/// use rust_nethuns_miri::SocketOptions;
+///
+/// let opts = SocketOptions::builder()
+///     .num_slots(1024)
+///     .frame_size(2048)
+///     .promisc(true)
+///     .build()
+///     .unwrap();
+/// assert_eq!(opts.num_slots(), 1024);
+/// ```
+#[derive(Debug, Clone)]
+pub struct SocketOptionsBuilder {
+    opts: SocketOptions,
+}
+
+impl SocketOptionsBuilder {
+    /// Set the number of slots of the ring.
+    pub fn num_slots(mut self, num_slots: usize) -> Self {
+        self.opts.num_slots = num_slots;
+        self
+    }
+
+    /// Set the size in bytes of each slot frame.
+    pub fn frame_size(mut self, frame_size: usize) -> Self {
+        self.opts.frame_size = frame_size;
+        self
+    }
+
+    /// Set the alignment in bytes of each slot frame,
+    /// which must be a power of two.
+    pub fn frame_align(mut self, frame_align: usize) -> Self {
+        self.opts.frame_align = frame_align;
+        self
+    }
+
+    /// Set the receive timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.opts.timeout = timeout;
+        self
+    }
+
+    /// Set the capture direction.
+    ///
+    /// The packets received by a [`Socket`](crate::Socket) whose
+    /// [`PacketHeader::direction`](crate::PacketHeader::direction) is
+    /// not captured are skipped.
+    pub fn dir(mut self, dir: CaptureDir) -> Self {
+        self.opts.dir = dir;
+        self
+    }
+
+    /// Enable or disable promiscuous mode.
+    pub fn promisc(mut self, promisc: bool) -> Self {
+        self.opts.promisc = promisc;
+        self
+    }
+
+    /// Enable or disable the computation of the RSS hash.
+    pub fn rxhash(mut self, rxhash: bool) -> Self {
+        self.opts.rxhash = rxhash;
+        self
+    }
+
+    /// Validate the options.
+    pub fn build(self) -> Result<SocketOptions, OptionsError> {
+        let opts = self.opts;
+
+        if opts.num_slots == 0 {
+            return Err(OptionsError::ZeroSlots);
+        }
+        if opts.num_slots > MAX_SLOTS {
+            return Err(OptionsError::TooManySlots {
+                requested: opts.num_slots,
+                max: MAX_SLOTS,
+            });
+        }
+        if opts.frame_size == 0 {
+            return Err(OptionsError::ZeroFrameSize);
+        }
+        if opts.frame_size > MAX_FRAME_SIZE {
+            return Err(OptionsError::FrameTooLarge {
+                requested: opts.frame_size,
+                max: MAX_FRAME_SIZE,
+            });
+        }
+        if !opts.frame_align.is_power_of_two()
+            || opts.frame_align > MAX_FRAME_ALIGN
+        {
+            return Err(OptionsError::BadFrameAlign {
+                requested: opts.frame_align,
+                max: MAX_FRAME_ALIGN,
+            });
+        }
+
+        Ok(opts)
+    }
+}
@@ -0,0 +1,361 @@
+//! Packets handed out by a [`Socket`](crate::Socket).
+
+use std::cell::UnsafeCell;
+use std::fmt::Display;
+use std::ops::{Deref, RangeBounds};
+use std::time::Duration;
+use std::{mem, vec};
+
+use crate::ring::TxRing;
+use crate::socket::ReentrancyGuard;
+use crate::status::{SlotState, SlotStatus};
+
+
+/// Direction of a packet with respect to the capturing interface.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    /// The packet was received by the interface
+    #[default]
+    Incoming,
+    /// The packet was transmitted by the interface
+    Outgoing,
+}
+
+
+/// Metadata of a received packet, modeled on Nethuns' `nethuns_pkthdr`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PacketHeader {
+    /// Capture timestamp, relative to the epoch of the packet source
+    /// (the Unix epoch for replayed traces, the creation of the ring
+    /// for synthetic packets)
+    pub timestamp: Duration,
+    /// Number of bytes stored in the slot
+    pub caplen: usize,
+    /// Length of the packet on the wire
+    pub len: usize,
+    /// Index of the interface the packet was captured on
+    pub ifindex: u32,
+    /// VLAN Tag Control Information, if the tag was stripped from the
+    /// frame by the source. None of the built-in backends strip tags,
+    /// so it is always `None` for them
+    pub vlan_tci: Option<u16>,
+    /// RSS hash, if enabled by
+    /// [`SocketOptions::rxhash`](crate::SocketOptions::rxhash)
+    pub rxhash: Option<u32>,
+    /// Packet direction, as recorded by a replayed pcapng trace
+    /// (`epb_flags`). Every other source reports incoming packets
+    pub direction: Direction,
+    /// Comment attached to the packet by a replayed trace, the first
+    /// one if the packet has several
+    pub comment: Option<String>,
+}
+
+
+/// Identifier of a received packet, modeled on Nethuns' `pkt_id`.
+///
+/// Unlike a [`RecvPacket`], an id does not borrow the socket, so it can
+/// be stored and used to release the packet later, possibly out of
+/// order, through [`Socket::release`](crate::Socket::release).
+///
+/// The id combines the index of the slot with its generation, i.e. the
+/// number of packets received into it, so that the id of a released
+/// packet is not mistaken for the one of the next packet received into
+/// the same slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PacketId {
+    pub(crate) idx: usize,
+    pub(crate) generation: u32,
+}
+
+impl PacketId {
+    /// Index of the ring slot holding the packet.
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    /// Generation of the slot when the packet was received.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Pack the id into an integer, with the generation in the upper
+    /// half and the slot index in the lower half, like a C `pkt_id`.
+    pub fn into_raw(self) -> u64 {
+        (self.generation as u64) << 32 | self.idx as u64
+    }
+
+    /// Unpack an id packed by [`PacketId::into_raw`].
+    pub fn from_raw(raw: u64) -> Self {
+        PacketId {
+            idx: (raw & u32::MAX as u64) as usize,
+            generation: (raw >> 32) as u32,
+        }
+    }
+}
+
+impl Display for PacketId {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "#{}@{}", self.idx, self.generation)
+    }
+}
+
+
+/// Structure which emulates a received packet in Nethuns.
+///
+/// The packet borrows the buffer of the ring slot it was received into.
+/// The slot is set as FREE again when the packet is dropped, unless the
+/// packet is detached with [`RecvPacket::into_id`] and later released
+/// by id.
+///
+/// # Threads
+///
+/// `RecvPacket` is `Send`: it can be received on an I/O thread and then
+/// processed and dropped on a worker thread, while the socket keeps
+/// receiving. Dropping the packet moves its slot from USER to FREE with
+/// release semantics, and the ring checks that the slot is FREE with
+/// acquire semantics before filling it again. Therefore every access to
+/// the payload made before the drop happens-before the slot is reused,
+/// on whichever thread the packet was dropped.
+#[derive(Debug)]
+pub struct RecvPacket<'a> {
+    pub(crate) idx: usize,
+    pub(crate) generation: u32,
+    pub(crate) status: &'a SlotStatus,
+    pub(crate) header: &'a PacketHeader,
+    pub(crate) packet: &'a [u8],
+}
+
+// `RecvPacket` must stay `Send`, see its documentation
+const _: fn() = || {
+    fn assert_send<T: Send>() {}
+    assert_send::<RecvPacket<'static>>();
+};
+
+impl<'a> RecvPacket<'a> {
+    /// Index of the ring slot holding the packet.
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    /// Packet metadata.
+    pub fn header(&self) -> &'a PacketHeader {
+        self.header
+    }
+
+    /// Packet payload.
+    pub fn packet(&self) -> &'a [u8] {
+        self.packet
+    }
+
+    /// Identifier of the packet.
+    pub fn id(&self) -> PacketId {
+        PacketId {
+            idx: self.idx,
+            generation: self.generation,
+        }
+    }
+
+    /// Give up the handle without releasing the slot, which stays
+    /// reserved until the returned id is passed to
+    /// [`Socket::release`](crate::Socket::release), or to
+    /// [`ShmSocket::release`](crate::ShmSocket::release) for the packets
+    /// of a shared-memory ring.
+    pub fn into_id(self) -> PacketId {
+        let id = self.id();
+        self.status
+            .advance(SlotState::User, SlotState::PendingRelease);
+        // The slot is now released by id
+        mem::forget(self);
+        id
+    }
+}
+
+impl Display for RecvPacket<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "idx: {:?}, status: {}, packet: {:?}",
+            self.idx,
+            self.status.load(),
+            self.packet
+        )
+    }
+}
+
+impl Drop for RecvPacket<'_> {
+    fn drop(&mut self) {
+        // Set the slot as FREE, since the corresponding RecvPacket
+        // will be destroyed. The transition has release semantics,
+        // which publishes the end of every access to the payload
+        // to the thread receiving into the slot next.
+        self.status.advance(SlotState::User, SlotState::Free);
+    }
+}
+
+
+/// Batch of packets filled by [`Socket::recv_burst`](crate::Socket::recv_burst).
+///
+/// The packets can be released together, by clearing or dropping the
+/// batch, or one by one, by taking them out of the batch. The batch can
+/// be reused across bursts, so that its buffer is allocated once.
+///
+/// ```
+/// use rust_nethuns_miri::{PacketBatch, Socket};
+///
+/// let socket = Socket::new();
+/// let mut batch = PacketBatch::with_capacity(4);
+/// assert_eq!(socket.recv_burst(&mut batch, 4).unwrap(), 4);
+///
+/// // Release the first packet alone...
+/// drop(batch.remove(0));
+/// // ... and then the others together
+/// batch.clear();
+/// ```
+#[derive(Debug, Default)]
+pub struct PacketBatch<'a> {
+    packets: Vec<RecvPacket<'a>>,
+}
+
+impl<'a> PacketBatch<'a> {
+    /// Create an empty batch.
+    pub fn new() -> Self {
+        PacketBatch {
+            packets: Vec::new(),
+        }
+    }
+
+    /// Create an empty batch which can hold `capacity`
+    /// packets without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        PacketBatch {
+            packets: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Release every packet of the batch.
+    pub fn clear(&mut self) {
+        self.packets.clear();
+    }
+
+    /// Take the last packet out of the batch.
+    pub fn pop(&mut self) -> Option<RecvPacket<'a>> {
+        self.packets.pop()
+    }
+
+    /// Take the packet at position `index` out of the batch.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> RecvPacket<'a> {
+        self.packets.remove(index)
+    }
+
+    /// Take the packets in `range` out of the batch.
+    pub fn drain<R>(&mut self, range: R) -> vec::Drain<'_, RecvPacket<'a>>
+    where
+        R: RangeBounds<usize>,
+    {
+        self.packets.drain(range)
+    }
+
+    pub(crate) fn extend(
+        &mut self,
+        packets: impl IntoIterator<Item = RecvPacket<'a>>,
+    ) {
+        self.packets.extend(packets);
+    }
+}
+
+impl<'a> Deref for PacketBatch<'a> {
+    type Target = [RecvPacket<'a>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.packets
+    }
+}
+
+impl<'a> IntoIterator for PacketBatch<'a> {
+    type Item = RecvPacket<'a>;
+    type IntoIter = vec::IntoIter<RecvPacket<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.into_iter()
+    }
+}
+
+impl<'b, 'a> IntoIterator for &'b PacketBatch<'a> {
+    type Item = &'b RecvPacket<'a>;
+    type IntoIter = std::slice::Iter<'b, RecvPacket<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.packets.iter()
+    }
+}
+
+/// Structure which emulates a TX slot reserved through
+/// `nethuns_get_buf_addr`, whose frame is built in place.
+///
+/// The frame is queued for transmission by [`TxSlot::commit`],
+/// which emulates `nethuns_send_slot`. If the slot is dropped
+/// without being committed, the reservation is aborted and
+/// the slot is set as FREE again.
+///
+/// Unlike [`RecvPacket`], a `TxSlot` is `!Send`, since committing or
+/// aborting it mutates the TX ring of the `!Sync` socket.
+#[derive(Debug)]
+pub struct TxSlot<'a> {
+    pub(crate) idx: usize,
+    pub(crate) packet: &'a mut [u8],
+    pub(crate) ring: &'a UnsafeCell<TxRing>,
+    pub(crate) guard: &'a ReentrancyGuard,
+}
+
+impl TxSlot<'_> {
+    /// Index of the reserved ring slot.
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    /// Frame buffer, as large as the ring frame size.
+    pub fn packet(&self) -> &[u8] {
+        self.packet
+    }
+
+    /// Mutable frame buffer, as large as the ring frame size.
+    pub fn packet_mut(&mut self) -> &mut [u8] {
+        self.packet
+    }
+
+    /// Queue the first `len` bytes of the frame buffer for transmission.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is larger than the frame buffer.
+    pub fn commit(self, len: usize) {
+        assert!(
+            len <= self.packet.len(),
+            "cannot commit {len} bytes from a {}-byte slot",
+            self.packet.len()
+        );
+        let entered = self.guard.enter();
+        // SAFETY: the socket owning the ring is `!Sync` and not
+        // re-entered, and `TxRing::commit` does not touch the frame
+        // buffer borrowed by this slot.
+        unsafe { (*self.ring.get()).commit(self.idx, len) };
+        drop(entered);
+        // The slot has been handed over to the ring:
+        // don't abort it
+        mem::forget(self);
+    }
+}
+
+impl Drop for TxSlot<'_> {
+    fn drop(&mut self) {
+        // Set the slot as FREE, since the frame
+        // has not been committed
+        let _entered = self.guard.enter();
+        // SAFETY: see `TxSlot::commit`.
+        unsafe { (*self.ring.get()).abort(self.idx) };
+    }
+}
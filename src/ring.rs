@@ -0,0 +1,639 @@
+//! Ring of packet slots.
+
+use std::time::{Duration, Instant};
+
+use crate::arena::FrameArena;
+use crate::backend::Backend;
+use crate::error::{RecvError, ReleaseError, SendError};
+use crate::options::{CaptureDir, SocketOptions};
+use crate::packet::{PacketHeader, PacketId, RecvPacket};
+use crate::stats::Stats;
+use crate::status::{SlotState, SlotStatus};
+
+
+/// Structure which emulates a Nethuns ring slot.
+#[derive(Debug)]
+pub struct RingSlot {
+    /// Slot status
+    pub(crate) status: SlotStatus,
+    /// Packet metadata
+    pub(crate) header: PacketHeader,
+    /// Timestamp when the packet was received
+    pub(crate) timestamp: Instant,
+    /// Number of packets received into the slot, which tells apart
+    /// the ids of the packets received into the same slot
+    pub(crate) generation: u32,
+}
+
+impl RingSlot {
+    /// Check whether the slot is free, i.e. no [`RecvPacket`]
+    /// is currently pointing to its buffer.
+    pub fn is_free(&self) -> bool {
+        self.state() == SlotState::Free
+    }
+
+    /// Current state of the slot.
+    pub fn state(&self) -> SlotState {
+        self.status.load()
+    }
+
+    /// Timestamp when the last packet was received into the slot.
+    pub fn timestamp(&self) -> Instant {
+        self.timestamp
+    }
+
+    /// Metadata of the last packet received into the slot.
+    pub fn header(&self) -> &PacketHeader {
+        &self.header
+    }
+
+    /// Number of packets received into the slot (wrapping).
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+
+/// Structure which emulates a Nethuns ring in RX mode.
+///
+/// The ring is implemented as a vector of ring slots
+/// and a index pointing to the next available slot
+/// (slot is available <==> status is FREE).
+/// The packet of slot `idx` is stored in frame `idx` of the ring
+/// [`FrameArena`].
+///
+/// The `next` index wrap around when reaching the end
+/// of the vector, in order to simulate a circular queue.
+#[derive(Debug)]
+pub struct Ring {
+    slots: Vec<RingSlot>,
+    frames: FrameArena,
+    next: usize,
+    stats: Stats,
+    /// Whether the RSS hash is computed
+    rxhash: bool,
+    /// Direction of the packets to be received
+    dir: CaptureDir,
+}
+
+impl Ring {
+    /// Create a new ring with the default geometry
+    /// (see [`SocketOptions::default`]).
+    pub fn new() -> Self {
+        Self::with_options(&SocketOptions::default())
+    }
+
+    /// Create a new ring with `opts.num_slots()` free slots
+    /// of `opts.frame_size()` bytes each.
+    /// The slots are filled by a [`Backend`] when packets are received.
+    pub fn with_options(opts: &SocketOptions) -> Self {
+        let mut slots: Vec<RingSlot> = Vec::with_capacity(opts.num_slots());
+        for _ in 0..opts.num_slots() {
+            slots.push(RingSlot {
+                status: SlotStatus::new(SlotState::Free),
+                header: PacketHeader::default(),
+                timestamp: Instant::now(),
+                generation: 0,
+            })
+        }
+
+        Ring {
+            slots,
+            frames: frame_arena(opts),
+            next: 0,
+            stats: Stats::default(),
+            rxhash: opts.rxhash(),
+            dir: opts.dir(),
+        }
+    }
+
+    /// Number of slots in the ring.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Check whether the ring has no slots.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Ring slots, in index order.
+    pub fn slots(&self) -> &[RingSlot] {
+        &self.slots
+    }
+
+    /// Storage of the slot frames.
+    pub fn frames(&self) -> &FrameArena {
+        &self.frames
+    }
+
+    /// Ring statistics.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Mutable references to the slot `idx` alone and to its frame.
+    ///
+    /// Indexing `slots` mutably would reborrow the whole slice, which
+    /// conflicts with the [`SlotStatus`] references that packets
+    /// owned by other threads use to release the other slots.
+    ///
+    /// # Safety
+    ///
+    /// The slot must be held by the ring or by the backend,
+    /// so that no packet borrows its frame.
+    unsafe fn slot_mut(&mut self, idx: usize) -> (&mut RingSlot, &mut [u8]) {
+        assert!(idx < self.slots.len());
+        // SAFETY: `idx` is in bounds, and `as_mut_ptr` does not
+        // create any intermediate reference to the slice.
+        let slot = unsafe { &mut *self.slots.as_mut_ptr().add(idx) };
+        (slot, unsafe { self.frames.frame_mut(idx) })
+    }
+
+    /// Hand the free slot `idx` over to `backend`, which fills
+    /// it with a new packet, and set it as held by the user.
+    /// Packets whose direction is not captured are skipped.
+    ///
+    /// If the backend fails, the slot is set as FREE again.
+    fn fill<B: Backend>(
+        &mut self,
+        idx: usize,
+        backend: &mut B,
+    ) -> Result<(), RecvError> {
+        self.slots[idx]
+            .status
+            .advance(SlotState::Free, SlotState::Backend);
+
+        let (rxhash, dir) = (self.rxhash, self.dir);
+        // SAFETY: the slot is held by the backend.
+        let (slot, frame) = unsafe { self.slot_mut(idx) };
+        loop {
+            slot.header = PacketHeader::default();
+            if let Err(err) = backend.fill(frame, &mut slot.header) {
+                slot.status.advance(SlotState::Backend, SlotState::Free);
+                return Err(err);
+            }
+            if dir.captures(slot.header.direction) {
+                break;
+            }
+        }
+        debug_assert!(slot.header.caplen <= frame.len());
+        slot.header.caplen = slot.header.caplen.min(frame.len());
+        // Mutate the ring slot to test if it's safe to mutate
+        // the socket structure while RecvPacket objects exist
+        slot.timestamp = Instant::now();
+        slot.generation = slot.generation.wrapping_add(1);
+        if rxhash && slot.header.rxhash.is_none() {
+            slot.header.rxhash = Some(fnv1a(&frame[..slot.header.caplen]));
+        }
+
+        // Set the slot as held by the user
+        self.slots[idx]
+            .status
+            .advance(SlotState::Backend, SlotState::User);
+        Ok(())
+    }
+
+    /// Packet handle for the slot `idx`, which is held by the user.
+    fn packet(&self, idx: usize) -> RecvPacket<'_> {
+        let slot = &self.slots[idx];
+        // SAFETY: the slot is held by the user, so its frame
+        // is not mutated until the packet is dropped.
+        let frame = unsafe { self.frames.frame(idx) };
+        RecvPacket {
+            idx,
+            generation: slot.generation,
+            status: &slot.status,
+            header: &slot.header,
+            packet: &frame[..slot.header.caplen],
+        }
+    }
+
+    /// Receive a packet from `backend`
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the next slot
+    /// is still held by a [`RecvPacket`] (which is counted in
+    /// [`Stats::rx_ring_full`]), or with the error returned by
+    /// [`Backend::fill`], such as [`RecvError::WouldBlock`] if it
+    /// has no packet available.
+    pub fn recv<B: Backend>(
+        &mut self,
+        backend: &mut B,
+    ) -> Result<RecvPacket<'_>, RecvError> {
+        let next_idx = self.next;
+
+        // Check if `next` slot is free, i.e. no RecvPacket object
+        // is currently pointing to its internal buffer
+        if !self.slots[next_idx].is_free() {
+            self.stats.rx_ring_full += 1;
+            return Err(RecvError::WouldBlock);
+        }
+
+        self.fill(next_idx, backend)?;
+        // Circularly increase the `next` index
+        self.next = (next_idx + 1) % self.slots.len();
+        self.stats.rx_packets += 1;
+
+        // Return the received packet
+        Ok(self.packet(next_idx))
+    }
+
+    /// Receive up to `max` packets from `backend` in one pass,
+    /// into the consecutive free slots starting at `next`.
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the next slot
+    /// is still held by a [`RecvPacket`] (and `max` is not zero).
+    /// The burst stops early if the backend fails, in which case
+    /// the error is only returned if no packet was received.
+    pub fn recv_burst<B: Backend>(
+        &mut self,
+        max: usize,
+        backend: &mut B,
+    ) -> Result<impl Iterator<Item = RecvPacket<'_>>, RecvError> {
+        let start = self.next;
+        let len = self.slots.len();
+
+        let free = (0..max.min(len))
+            .take_while(|k| self.slots[(start + k) % len].is_free())
+            .count();
+        if free == 0 && max > 0 {
+            self.stats.rx_ring_full += 1;
+            return Err(RecvError::WouldBlock);
+        }
+
+        let mut count = 0;
+        while count < free {
+            match self.fill((start + count) % len, backend) {
+                Ok(()) => count += 1,
+                Err(err) if count == 0 => return Err(err),
+                Err(_) => break,
+            }
+        }
+        self.next = (start + count) % len;
+        self.stats.rx_packets += count as u64;
+
+        let ring = &*self;
+        Ok((0..count).map(move |k| ring.packet((start + k) % len)))
+    }
+
+    /// Slot holding the packet `id`, as long as it has not been
+    /// reused for another packet.
+    fn slot_of(&self, id: PacketId) -> Result<&RingSlot, ReleaseError> {
+        let slot = self.slots.get(id.idx).ok_or(ReleaseError::UnknownId(id))?;
+        if slot.generation != id.generation {
+            return Err(ReleaseError::Stale(id));
+        }
+        Ok(slot)
+    }
+
+    /// Current state of the slot holding the packet `id`.
+    ///
+    /// Fails with [`ReleaseError::Stale`] if the slot has been
+    /// reused for another packet since.
+    pub fn lookup(&self, id: PacketId) -> Result<SlotState, ReleaseError> {
+        self.slot_of(id).map(RingSlot::state)
+    }
+
+    /// Ids of the packets which have been held by the user, through a
+    /// handle or an id, for at least `deadline` since they were received.
+    ///
+    /// A packet whose handle has been leaked (e.g. with [`mem::forget`])
+    /// holds its slot forever, which eventually stalls the ring: this
+    /// reports such packets, in slot order.
+    ///
+    /// [`mem::forget`]: std::mem::forget
+    pub fn held_longer_than(&self, deadline: Duration) -> Vec<PacketId> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| {
+                matches!(
+                    slot.state(),
+                    SlotState::User | SlotState::PendingRelease
+                ) && slot.timestamp.elapsed() >= deadline
+            })
+            .map(|(idx, slot)| PacketId {
+                idx,
+                generation: slot.generation,
+            })
+            .collect()
+    }
+
+    /// Free the slot of the packet `id`, even if it is still
+    /// held by a handle.
+    ///
+    /// Fails like [`Ring::release`], except that packets held
+    /// by a handle are reclaimed too.
+    ///
+    /// # Safety
+    ///
+    /// The handle of the packet, if any, must never be used or dropped
+    /// again, i.e. it must have been leaked: the slot is going to be
+    /// refilled while the handle still borrows it.
+    pub unsafe fn force_reclaim(
+        &self,
+        id: PacketId,
+    ) -> Result<(), ReleaseError> {
+        let slot = self.slot_of(id)?;
+        let state = slot.state();
+        match state {
+            SlotState::User | SlotState::PendingRelease => slot
+                .status
+                .transition(state, SlotState::Free)
+                .map_err(|_| ReleaseError::Stale(id)),
+            SlotState::Free => Err(ReleaseError::DoubleRelease(id)),
+            _ => Err(ReleaseError::Stale(id)),
+        }
+    }
+
+    /// Release the packet `id`, which was detached from its handle
+    /// by [`RecvPacket::into_id`].
+    ///
+    /// Fails with [`ReleaseError::Stale`] if the slot has been
+    /// reused for another packet since.
+    pub fn release(&self, id: PacketId) -> Result<(), ReleaseError> {
+        // The generation cannot change in between, since slots
+        // are only refilled through `&mut self`
+        let slot = self.slot_of(id)?;
+        // Set the slot as FREE, with release semantics
+        // like the drop of a packet handle
+        slot.status
+            .transition(SlotState::PendingRelease, SlotState::Free)
+            .map_err(|err| match err.actual {
+                SlotState::Free => ReleaseError::DoubleRelease(id),
+                SlotState::User => ReleaseError::HandleAlive(id),
+                _ => ReleaseError::Stale(id),
+            })
+    }
+}
+
+impl Default for Ring {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Frame arena with the geometry described by `opts`.
+fn frame_arena(opts: &SocketOptions) -> FrameArena {
+    FrameArena::new(opts.num_slots(), opts.frame_size(), opts.frame_align())
+}
+
+
+/// Synthetic RSS hash: 32-bit FNV-1a of the whole packet.
+fn fnv1a(packet: &[u8]) -> u32 {
+    packet.iter().fold(0x811c9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+
+/// Structure which emulates a Nethuns ring slot in TX mode.
+#[derive(Debug)]
+pub struct TxRingSlot {
+    /// Slot status
+    pub(crate) status: SlotStatus,
+    /// Length of the frame stored in the slot
+    pub(crate) len: usize,
+}
+
+impl TxRingSlot {
+    /// Check whether the slot can be filled with a new frame.
+    pub fn is_free(&self) -> bool {
+        self.state() == SlotState::Free
+    }
+
+    /// Current state of the slot.
+    pub fn state(&self) -> SlotState {
+        self.status.load()
+    }
+
+    /// Length of the frame stored in the slot.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the frame stored in the slot is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+
+/// Structure which emulates a Nethuns ring in TX mode.
+///
+/// Slots move from free to in-flight when a frame is copied into them
+/// ([`TxRing::send`]), from in-flight to pending release when the frame
+/// is transmitted ([`TxRing::flush`]) and from pending release back to
+/// free when they are reclaimed ([`TxRing::complete`]). The three steps
+/// follow the ring order, so three indexes are enough to track them:
+/// `head` (next slot to fill), `pending` (next slot to flush) and `tail`
+/// (next slot to reclaim). See [`SlotState`] for the whole lifecycle.
+///
+/// A frame can also be built in place, through the
+/// [`TxSlot`](crate::TxSlot) returned by
+/// [`Socket::reserve_tx`](crate::Socket::reserve_tx): the slot at `head`
+/// is reserved, which makes it held by the user, and then either
+/// committed, which makes it in-flight, or aborted, which makes it free
+/// again. `head` only moves on commit, so at most one slot can be
+/// reserved at a time and aborting never leaves a hole in the ring.
+#[derive(Debug)]
+pub struct TxRing {
+    slots: Vec<TxRingSlot>,
+    frames: FrameArena,
+    head: usize,
+    pending: usize,
+    tail: usize,
+    stats: Stats,
+}
+
+impl TxRing {
+    /// Create a new TX ring with `opts.num_slots()` free slots
+    /// of `opts.frame_size()` bytes each.
+    pub fn with_options(opts: &SocketOptions) -> Self {
+        let mut slots: Vec<TxRingSlot> = Vec::with_capacity(opts.num_slots());
+        for _ in 0..opts.num_slots() {
+            slots.push(TxRingSlot {
+                status: SlotStatus::new(SlotState::Free),
+                len: 0,
+            })
+        }
+
+        TxRing {
+            slots,
+            frames: frame_arena(opts),
+            head: 0,
+            pending: 0,
+            tail: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Number of slots in the ring.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Check whether the ring has no slots.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Ring slots, in index order.
+    pub fn slots(&self) -> &[TxRingSlot] {
+        &self.slots
+    }
+
+    /// Storage of the slot frames.
+    pub fn frames(&self) -> &FrameArena {
+        &self.frames
+    }
+
+    /// Frame stored in the slot `idx`, if it is queued or transmitted.
+    pub fn frame(&self, idx: usize) -> Option<&[u8]> {
+        let slot = self.slots.get(idx)?;
+        match slot.state() {
+            SlotState::InFlight | SlotState::PendingRelease => {
+                // SAFETY: the frame of a queued or transmitted slot
+                // is not mutated until the slot is reclaimed, which
+                // requires `&mut self`.
+                let frame = unsafe { self.frames.frame(idx) };
+                Some(&frame[..slot.len])
+            }
+            _ => None,
+        }
+    }
+
+    /// Ring statistics.
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Check whether the slot at `head` can be filled.
+    fn check_head(&mut self) -> Result<usize, SendError> {
+        let head = self.head;
+        // The slot can be reused only once its previous frame
+        // has been transmitted and reclaimed
+        if !self.slots[head].is_free() {
+            self.stats.tx_ring_full += 1;
+            return Err(SendError::RingFull);
+        }
+        Ok(head)
+    }
+
+    /// Copy `frame` into the next free slot and queue it for transmission.
+    pub fn send(&mut self, frame: &[u8]) -> Result<(), SendError> {
+        let max = self.frames.frame_size();
+        if frame.len() > max {
+            return Err(SendError::FrameTooLarge {
+                len: frame.len(),
+                max,
+            });
+        }
+        let head = self.check_head()?;
+
+        let slot = &mut self.slots[head];
+        // The user owns the slot while the frame is copied
+        slot.status.advance(SlotState::Free, SlotState::User);
+        // SAFETY: the slot was free, so nothing borrows its frame.
+        let buf = unsafe { self.frames.frame_mut(head) };
+        buf[..frame.len()].copy_from_slice(frame);
+        slot.len = frame.len();
+        // Queue the frame for transmission
+        slot.status.advance(SlotState::User, SlotState::InFlight);
+        self.head = (head + 1) % self.slots.len();
+
+        Ok(())
+    }
+
+    /// Reserve the next free slot, so that a frame can be built in place.
+    ///
+    /// Returns the slot index and its whole frame buffer.
+    /// The slot must be later passed to either [`TxRing::commit`]
+    /// or [`TxRing::abort`].
+    pub(crate) fn reserve(&mut self) -> Result<(usize, &mut [u8]), SendError> {
+        let head = self.check_head()?;
+
+        // Set the slot as held by the user
+        self.slots[head]
+            .status
+            .advance(SlotState::Free, SlotState::User);
+        // SAFETY: the slot was free, so nothing borrows its frame,
+        // and it is not touched again until committed or aborted.
+        Ok((head, unsafe { self.frames.frame_mut(head) }))
+    }
+
+    /// Queue the first `len` bytes of the reserved slot `idx`
+    /// for transmission.
+    ///
+    /// `len` must not exceed the frame size, which `TxSlot` checks.
+    pub(crate) fn commit(&mut self, idx: usize, len: usize) {
+        debug_assert_eq!(idx, self.head, "only the head slot can be reserved");
+        let slot = &mut self.slots[idx];
+
+        slot.len = len;
+        // Queue the frame for transmission
+        slot.status.advance(SlotState::User, SlotState::InFlight);
+        self.head = (idx + 1) % self.slots.len();
+    }
+
+    /// Give back the reserved slot `idx` without transmitting it.
+    pub(crate) fn abort(&mut self, idx: usize) {
+        debug_assert_eq!(idx, self.head, "only the head slot can be reserved");
+        // Set the slot as FREE
+        self.slots[idx]
+            .status
+            .advance(SlotState::User, SlotState::Free);
+    }
+
+    /// Transmit every in-flight frame through `backend`, in ring order.
+    ///
+    /// Frames which the backend fails to transmit are dropped, and their
+    /// slots are reclaimed like the ones of transmitted frames.
+    ///
+    /// Returns the number of flushed frames.
+    pub fn flush<B: Backend>(&mut self, backend: &mut B) -> usize {
+        let mut count = 0;
+        while count < self.slots.len() {
+            let slot = &self.slots[self.pending];
+            if slot.state() != SlotState::InFlight {
+                break;
+            }
+            // Hand the slot over to the backend,
+            // which completes it once transmitted
+            slot.status.advance(SlotState::InFlight, SlotState::Backend);
+            // SAFETY: the slot is held by the backend,
+            // so nothing mutates its frame.
+            let frame = unsafe { self.frames.frame(self.pending) };
+            match backend.transmit(&frame[..slot.len]) {
+                Ok(()) => self.stats.tx_packets += 1,
+                Err(_) => self.stats.tx_dropped += 1,
+            }
+            slot.status
+                .advance(SlotState::Backend, SlotState::PendingRelease);
+            self.pending = (self.pending + 1) % self.slots.len();
+            count += 1;
+        }
+        count
+    }
+
+    /// Reclaim every transmitted slot, in ring order,
+    /// so that it can be filled again.
+    ///
+    /// Returns the number of reclaimed slots.
+    pub fn complete(&mut self) -> usize {
+        let mut count = 0;
+        while count < self.slots.len() {
+            let slot = &self.slots[self.tail];
+            if slot.state() != SlotState::PendingRelease {
+                break;
+            }
+            // Set the slot as FREE
+            slot.status
+                .advance(SlotState::PendingRelease, SlotState::Free);
+            self.tail = (self.tail + 1) % self.slots.len();
+            count += 1;
+        }
+        count
+    }
+}
@@ -0,0 +1,205 @@
+//! Socket which can be shared across threads.
+
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use crate::backend::{Backend, MemoryBackend};
+use crate::error::{RecvError, ReleaseError, SendError};
+use crate::options::SocketOptions;
+use crate::packet::{PacketBatch, PacketId, RecvPacket};
+use crate::socket::{self, Socket};
+use crate::stats::Stats;
+use crate::status::SlotState;
+
+
+/// Thread-safe variant of [`Socket`], whose `recv` can be
+/// called concurrently by several worker threads.
+///
+/// Every operation on the rings is serialized by an internal lock,
+/// which is only held while a slot is claimed or a frame is queued:
+/// the returned [`RecvPacket`]s are not tied to the lock, so they can
+/// be processed and dropped in parallel. Releasing a packet only
+/// touches the status of its own slot, hence it never takes the lock.
+///
+/// In-place transmission through [`Socket::reserve_tx`] is not
+/// available, since a [`TxSlot`](crate::TxSlot) would touch the
+/// TX ring outside of the lock when committed.
+///
+/// ```
+/// use std::thread;
+///
+/// use rust_nethuns_miri::SharedSocket;
+///
+/// let socket = SharedSocket::new();
+/// thread::scope(|s| {
+///     for _ in 0..2 {
+///         s.spawn(|| {
+///             let packet = socket.recv().unwrap();
+///             assert_eq!(packet.packet().len(), 5);
+///         });
+///     }
+/// });
+/// assert_eq!(socket.stats().rx_packets, 2);
+/// ```
+#[derive(Debug)]
+pub struct SharedSocket<B: Backend = MemoryBackend> {
+    socket: Socket<B>,
+    /// Serializes every access to the rings
+    lock: Mutex<()>,
+}
+
+// SAFETY: every method which mutates the rings or the backend of `socket`
+// holds `lock`, and the handles they return only touch the status of their
+// own slot. The backend may be used by different threads, hence it must be
+// `Send`.
+unsafe impl<B: Backend + Send> Sync for SharedSocket<B> {}
+
+impl SharedSocket {
+    /// Create a new synthetic shared socket with the default options
+    pub fn new() -> Self {
+        Self::new_with(SocketOptions::default())
+    }
+
+    /// Create a new synthetic shared socket whose ring geometry
+    /// is described by `opts`
+    pub fn new_with(opts: SocketOptions) -> Self {
+        Socket::new_with(opts).into()
+    }
+}
+
+impl<B: Backend> SharedSocket<B> {
+    fn lock(&self) -> MutexGuard<'_, ()> {
+        // The lock guards no data, so a poisoned lock can be reused
+        self.lock.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Receive a packet, waiting up to the socket timeout.
+    ///
+    /// The lock is not held while waiting. See [`Socket::recv`].
+    pub fn recv(&self) -> Result<RecvPacket<'_>, RecvError> {
+        socket::wait_for(self.socket.timeout(), || self.try_recv())
+    }
+
+    /// Receive a packet without waiting.
+    ///
+    /// See [`Socket::try_recv`].
+    pub fn try_recv(&self) -> Result<RecvPacket<'_>, RecvError> {
+        let _guard = self.lock();
+        self.socket.try_recv()
+    }
+
+    /// Receive up to `max` packets in one pass, waiting up to the
+    /// socket timeout.
+    ///
+    /// The lock is taken once per burst and is not held while
+    /// waiting. See [`Socket::recv_burst`].
+    pub fn recv_burst<'a>(
+        &'a self,
+        batch: &mut PacketBatch<'a>,
+        max: usize,
+    ) -> Result<usize, RecvError> {
+        socket::wait_for(self.socket.timeout(), || {
+            self.try_recv_burst(batch, max)
+        })
+    }
+
+    /// Receive up to `max` packets in one pass, without waiting.
+    ///
+    /// See [`Socket::try_recv_burst`].
+    pub fn try_recv_burst<'a>(
+        &'a self,
+        batch: &mut PacketBatch<'a>,
+        max: usize,
+    ) -> Result<usize, RecvError> {
+        let _guard = self.lock();
+        self.socket.try_recv_burst(batch, max)
+    }
+
+    /// Release the packet `id`, detached from its handle.
+    ///
+    /// See [`Socket::release`].
+    pub fn release(&self, id: PacketId) -> Result<(), ReleaseError> {
+        let _guard = self.lock();
+        self.socket.release(id)
+    }
+
+    /// Current state of the slot holding the packet `id`.
+    ///
+    /// See [`Socket::lookup`].
+    pub fn lookup(&self, id: PacketId) -> Result<SlotState, ReleaseError> {
+        let _guard = self.lock();
+        self.socket.lookup(id)
+    }
+
+    /// Ids of the packets held by the user for at least `deadline`.
+    ///
+    /// See [`Socket::held_longer_than`].
+    pub fn held_longer_than(&self, deadline: Duration) -> Vec<PacketId> {
+        let _guard = self.lock();
+        self.socket.held_longer_than(deadline)
+    }
+
+    /// Free the slot of the leaked packet `id`.
+    ///
+    /// # Safety
+    ///
+    /// See [`Socket::force_reclaim`].
+    pub unsafe fn force_reclaim(
+        &self,
+        id: PacketId,
+    ) -> Result<(), ReleaseError> {
+        let _guard = self.lock();
+        // SAFETY: guaranteed by the caller.
+        unsafe { self.socket.force_reclaim(id) }
+    }
+
+    /// Copy `frame` into the TX ring and queue it for transmission.
+    ///
+    /// See [`Socket::send`].
+    pub fn send(&self, frame: &[u8]) -> Result<(), SendError> {
+        let _guard = self.lock();
+        self.socket.send(frame)
+    }
+
+    /// Transmit every queued frame.
+    ///
+    /// See [`Socket::flush`].
+    pub fn flush(&self) -> usize {
+        let _guard = self.lock();
+        self.socket.flush()
+    }
+
+    /// Reclaim the TX slots whose frames have been transmitted.
+    ///
+    /// See [`Socket::complete_tx`].
+    pub fn complete_tx(&self) -> usize {
+        let _guard = self.lock();
+        self.socket.complete_tx()
+    }
+
+    /// Socket statistics.
+    pub fn stats(&self) -> Stats {
+        let _guard = self.lock();
+        self.socket.stats()
+    }
+
+    /// Unwrap the inner, single-threaded socket.
+    pub fn into_inner(self) -> Socket<B> {
+        self.socket
+    }
+}
+
+impl Default for SharedSocket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<B: Backend> From<Socket<B>> for SharedSocket<B> {
+    fn from(socket: Socket<B>) -> Self {
+        SharedSocket {
+            socket,
+            lock: Mutex::new(()),
+        }
+    }
+}
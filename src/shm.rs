@@ -0,0 +1,500 @@
+//! Rings shared between processes through a file-backed mapping,
+//! whose packets are received in place.
+
+use std::cell::{Cell, UnsafeCell};
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::arena::FrameArena;
+use crate::backend::wall_clock;
+use crate::error::{RecvError, ReleaseError, SendError};
+use crate::options::{SocketOptions, MAX_FRAME_SIZE, MAX_SLOTS};
+use crate::packet::{PacketHeader, PacketId, RecvPacket};
+use crate::stats::Stats;
+use crate::status::{SlotState, SlotStatus};
+
+
+/// Magic number at the start of a shared-memory ring ("NTHS").
+pub const SHM_MAGIC: u32 = 0x4e54_4853;
+
+/// Size of the ring header, which is followed by the slot descriptors.
+const HEADER_SIZE: usize = 64;
+/// Size of a slot descriptor: status byte, length and timestamp.
+const DESCRIPTOR_SIZE: usize = 16;
+/// Alignment of the frame area.
+const FRAME_ALIGN: usize = 64;
+
+
+/// Shared, writable mapping of a file.
+#[derive(Debug)]
+struct Mapping {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: the mapping is plain memory, which is only
+// accessed through the ring protocol.
+unsafe impl Send for Mapping {}
+
+impl Mapping {
+    fn new(file: &File, len: usize) -> io::Result<Self> {
+        // SAFETY: a fresh mapping is requested, so no
+        // existing memory is affected.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let ptr = NonNull::new(ptr.cast())
+            .ok_or_else(|| io::Error::other("mmap returned null"))?;
+        Ok(Mapping { ptr, len })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created by `Mapping::new`, and no
+        // reference into it outlives the ring which owns it.
+        unsafe { libc::munmap(self.ptr.as_ptr().cast(), self.len) };
+    }
+}
+
+
+/// Memory holding a ring.
+#[derive(Debug)]
+enum Region {
+    /// Shared mapping of a file
+    Mapped(Mapping),
+    /// Heap buffer shared by the views of a ring within a process,
+    /// held as the single frame of an arena
+    Heap(Arc<FrameArena>),
+}
+
+impl Region {
+    /// Start of the region.
+    fn as_ptr(&self) -> *mut u8 {
+        match self {
+            Region::Mapped(map) => map.ptr.as_ptr(),
+            // Writable, like the frames the arena hands out from it
+            Region::Heap(buf) => buf.as_ptr().cast_mut(),
+        }
+    }
+}
+
+
+/// Ring of frames living in a file-backed shared mapping
+/// (e.g. a file under `/dev/shm`), modeled on the mmap'ed rings of
+/// AF_PACKET.
+///
+/// The mapping starts with a header (magic number, number of slots,
+/// frame size), followed by one descriptor per slot and by the frame
+/// area. Each descriptor holds a status byte, the frame length and its
+/// timestamp. The status byte is the [`SlotStatus`] of the slot, which
+/// hands it over like the `tp_status` word of AF_PACKET:
+///
+/// - the producer fills a [`SlotState::Free`] slot, and publishes it
+///   as [`SlotState::Backend`] with a release transition;
+/// - the consumer takes a published slot after an acquire load, and
+///   holds it as [`SlotState::User`] (or
+///   [`SlotState::PendingRelease`]) while its packet is alive;
+/// - releasing the packet frees the slot, with a release transition.
+///
+/// Both sides walk the slots in order, so no other state is shared.
+///
+/// A ring has a single producer ([`ShmProducer`]) and a single
+/// consumer ([`ShmSocket`]), usually in different processes. A ring
+/// can also live in a heap buffer ([`ShmRing::pair`]), to check the
+/// protocol between two threads under Miri, which cannot emulate
+/// `mmap`.
+///
+/// Only available on Unix, where files can be mapped with `mmap`.
+#[derive(Debug)]
+pub struct ShmRing {
+    region: Region,
+    num_slots: usize,
+    frame_size: usize,
+}
+
+impl ShmRing {
+    /// Create the file at `path`, replacing any existing one, and lay
+    /// out an empty ring with the geometry described by `opts`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        opts: &SocketOptions,
+    ) -> io::Result<Self> {
+        let (num_slots, frame_size) = (opts.num_slots(), opts.frame_size());
+        let len = Self::size(num_slots, frame_size);
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        // The new file is zero-filled, so every slot is free
+        file.set_len(len as u64)?;
+
+        Ok(Self::init(
+            Region::Mapped(Mapping::new(&file, len)?),
+            num_slots,
+            frame_size,
+        ))
+    }
+
+    /// Lay out an empty ring with the geometry described by `opts` in a
+    /// heap buffer, and return two views of it, e.g. for a producer
+    /// and a consumer running on different threads.
+    pub fn pair(opts: &SocketOptions) -> (Self, Self) {
+        let (num_slots, frame_size) = (opts.num_slots(), opts.frame_size());
+        let len = Self::size(num_slots, frame_size);
+        // The arena is zero-filled, so every slot is free
+        let buf = Arc::new(FrameArena::new(1, len, FRAME_ALIGN));
+        let ring =
+            Self::init(Region::Heap(Arc::clone(&buf)), num_slots, frame_size);
+        let view = ShmRing {
+            region: Region::Heap(buf),
+            num_slots,
+            frame_size,
+        };
+        (ring, view)
+    }
+
+    /// Write the header of a new ring at the start of `region`,
+    /// which is zero-filled and at least `Self::size` bytes long.
+    fn init(region: Region, num_slots: usize, frame_size: usize) -> Self {
+        let header = region.as_ptr().cast::<u32>();
+        // SAFETY: the region is at least `HEADER_SIZE` bytes long
+        // and aligned, and nobody uses the new ring yet.
+        unsafe {
+            header.add(1).write(num_slots as u32);
+            header.add(2).write(frame_size as u32);
+            header.write(SHM_MAGIC);
+        }
+        ShmRing {
+            region,
+            num_slots,
+            frame_size,
+        }
+    }
+
+    /// Map the ring created at `path` by [`ShmRing::create`].
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let file_len = file.metadata()?.len() as usize;
+        if file_len < HEADER_SIZE {
+            return Err(invalid("the file is too short for a ring header"));
+        }
+
+        let header = Mapping::new(&file, HEADER_SIZE)?;
+        let fields = header.ptr.as_ptr().cast::<u32>();
+        // SAFETY: the mapping is `HEADER_SIZE` bytes long and aligned.
+        let (magic, num_slots, frame_size) = unsafe {
+            (
+                fields.read(),
+                fields.add(1).read() as usize,
+                fields.add(2).read() as usize,
+            )
+        };
+        if magic != SHM_MAGIC {
+            return Err(invalid("bad shared-memory ring magic"));
+        }
+        if !(1..=MAX_SLOTS).contains(&num_slots)
+            || !(1..=MAX_FRAME_SIZE).contains(&frame_size)
+        {
+            return Err(invalid("bad shared-memory ring geometry"));
+        }
+        let len = Self::size(num_slots, frame_size);
+        if file_len < len {
+            return Err(invalid("the file is too short for the ring"));
+        }
+
+        Ok(ShmRing {
+            region: Region::Mapped(Mapping::new(&file, len)?),
+            num_slots,
+            frame_size,
+        })
+    }
+
+    /// Number of slots.
+    pub fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    /// Size of each frame.
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    /// Size of the mapping of a ring with the given geometry.
+    fn size(num_slots: usize, frame_size: usize) -> usize {
+        Self::frames_offset(num_slots) + num_slots * frame_size
+    }
+
+    fn frames_offset(num_slots: usize) -> usize {
+        (HEADER_SIZE + num_slots * DESCRIPTOR_SIZE)
+            .next_multiple_of(FRAME_ALIGN)
+    }
+
+    fn descriptor(&self, idx: usize) -> *mut u8 {
+        debug_assert!(idx < self.num_slots);
+        // SAFETY: the descriptors lie within the region.
+        unsafe {
+            self.region
+                .as_ptr()
+                .add(HEADER_SIZE + idx * DESCRIPTOR_SIZE)
+        }
+    }
+
+    fn status(&self, idx: usize) -> &SlotStatus {
+        // SAFETY: the status byte lies within the region, which
+        // outlives the returned reference, and is only ever accessed
+        // atomically by both sides.
+        unsafe { SlotStatus::from_ptr(self.descriptor(idx)) }
+    }
+
+    fn frame(&self, idx: usize) -> *mut u8 {
+        // SAFETY: the frames lie within the region.
+        unsafe {
+            self.region.as_ptr().add(
+                Self::frames_offset(self.num_slots) + idx * self.frame_size,
+            )
+        }
+    }
+}
+
+
+/// Producing side of a [`ShmRing`].
+#[derive(Debug)]
+pub struct ShmProducer {
+    ring: ShmRing,
+    next: usize,
+}
+
+impl ShmProducer {
+    /// Produce frames into `ring`, starting from its first slot.
+    pub fn new(ring: ShmRing) -> Self {
+        ShmProducer { ring, next: 0 }
+    }
+
+    /// Copy `frame` into the next slot and publish it to the consumer,
+    /// timestamped with the wall-clock time since the Unix epoch.
+    ///
+    /// Fails with [`SendError::RingFull`] if the consumer has not
+    /// released the packet of the next slot yet.
+    pub fn send(&mut self, frame: &[u8]) -> Result<(), SendError> {
+        self.send_at(frame, wall_clock())
+    }
+
+    /// Copy `frame` into the next slot and publish it to the consumer,
+    /// with the given `timestamp`.
+    ///
+    /// Fails like [`ShmProducer::send`].
+    pub fn send_at(
+        &mut self,
+        frame: &[u8],
+        timestamp: Duration,
+    ) -> Result<(), SendError> {
+        let idx = self.next;
+        if frame.len() > self.ring.frame_size {
+            return Err(SendError::FrameTooLarge {
+                len: frame.len(),
+                max: self.ring.frame_size,
+            });
+        }
+        // Acquire: the consumer is done reading the frame
+        let status = self.ring.status(idx);
+        if status.load() != SlotState::Free {
+            return Err(SendError::RingFull);
+        }
+
+        let descriptor = self.ring.descriptor(idx);
+        // SAFETY: the slot is free, so the consumer does not touch it
+        // until it is published below.
+        unsafe {
+            ptr::copy_nonoverlapping(
+                frame.as_ptr(),
+                self.ring.frame(idx),
+                frame.len(),
+            );
+            descriptor.add(4).cast::<u32>().write(frame.len() as u32);
+            descriptor
+                .add(8)
+                .cast::<u64>()
+                .write(timestamp.as_nanos() as u64);
+        }
+        // Release: publish the frame with its descriptor
+        status.advance(SlotState::Free, SlotState::Backend);
+        self.next = (idx + 1) % self.ring.num_slots;
+        Ok(())
+    }
+
+    /// Underlying ring.
+    pub fn ring(&self) -> &ShmRing {
+        &self.ring
+    }
+}
+
+
+/// Metadata of the packet held in a slot of a [`ShmSocket`],
+/// private to the consumer.
+#[derive(Debug, Default)]
+struct ShmSlot {
+    header: PacketHeader,
+    generation: u32,
+}
+
+
+/// Consuming side of a [`ShmRing`], which receives packets in place.
+///
+/// Every packet is a [`RecvPacket`] which borrows its frame in the
+/// shared mapping, and whose slot status is the status byte of the
+/// shared descriptor: dropping the packet, or releasing its id through
+/// [`ShmSocket::release`], hands the slot back to the producer. Frames
+/// are never copied, so a producer which is not drained stalls.
+///
+/// Like a [`Socket`](crate::Socket), the consumer is `!Sync`.
+///
+/// ```no_run
+/// use rust_nethuns_miri::{ShmRing, ShmSocket};
+///
+/// let socket = ShmSocket::new(ShmRing::open("/dev/shm/ring")?);
+/// let packet = socket.recv().unwrap();
+/// println!("{} bytes", packet.packet().len());
+/// // The producer can reuse the slot from now on
+/// drop(packet);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct ShmSocket {
+    ring: ShmRing,
+    slots: Box<[UnsafeCell<ShmSlot>]>,
+    next: Cell<usize>,
+    stats: Cell<Stats>,
+}
+
+impl ShmSocket {
+    /// Consume frames from `ring`, starting from its first slot.
+    pub fn new(ring: ShmRing) -> Self {
+        ShmSocket {
+            slots: (0..ring.num_slots).map(|_| Default::default()).collect(),
+            ring,
+            next: Cell::new(0),
+            stats: Cell::new(Stats::default()),
+        }
+    }
+
+    /// Underlying ring.
+    pub fn ring(&self) -> &ShmRing {
+        &self.ring
+    }
+
+    /// Receive the next packet published by the producer.
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the producer has not
+    /// published it yet, or if the packet previously received into the
+    /// same slot is still held (which is counted in
+    /// [`Stats::rx_ring_full`]).
+    pub fn recv(&self) -> Result<RecvPacket<'_>, RecvError> {
+        let idx = self.next.get();
+        let status = self.ring.status(idx);
+        let mut stats = self.stats.get();
+        // Acquire: see the frame written before the slot was published
+        match status.load() {
+            SlotState::Backend => {}
+            SlotState::Free => return Err(RecvError::WouldBlock),
+            _ => {
+                stats.rx_ring_full += 1;
+                self.stats.set(stats);
+                return Err(RecvError::WouldBlock);
+            }
+        }
+
+        let descriptor = self.ring.descriptor(idx);
+        // SAFETY: the slot is published, so the producer does not touch
+        // it until it is freed. The length is clamped to the frame
+        // size, in case the other side is misbehaving.
+        let (len, timestamp) = unsafe {
+            (
+                (descriptor.add(4).cast::<u32>().read() as usize)
+                    .min(self.ring.frame_size),
+                Duration::from_nanos(descriptor.add(8).cast::<u64>().read()),
+            )
+        };
+        // SAFETY: the socket is `!Sync`, and the packet previously held
+        // in the slot has been released, so nothing borrows its metadata.
+        let slot = unsafe { &mut *self.slots[idx].get() };
+        slot.header = PacketHeader {
+            timestamp,
+            caplen: len,
+            len,
+            ..PacketHeader::default()
+        };
+        slot.generation = slot.generation.wrapping_add(1);
+        status.advance(SlotState::Backend, SlotState::User);
+
+        self.next.set((idx + 1) % self.ring.num_slots);
+        stats.rx_packets += 1;
+        self.stats.set(stats);
+        Ok(RecvPacket {
+            idx,
+            generation: slot.generation,
+            status,
+            header: &slot.header,
+            // SAFETY: the slot is held by the user, so the producer
+            // does not write to the frame until the packet is released.
+            packet: unsafe { slice::from_raw_parts(self.ring.frame(idx), len) },
+        })
+    }
+
+    /// Release the packet `id`, which was detached from its handle
+    /// by [`RecvPacket::into_id`], and hand its slot back to the
+    /// producer.
+    ///
+    /// Fails like [`Socket::release`](crate::Socket::release).
+    pub fn release(&self, id: PacketId) -> Result<(), ReleaseError> {
+        let generation = self
+            .slots
+            .get(id.idx)
+            // SAFETY: see `ShmSocket::recv`, the metadata is only
+            // written by `recv` on this same thread.
+            .map(|slot| unsafe { (*slot.get()).generation })
+            .ok_or(ReleaseError::UnknownId(id))?;
+        if generation != id.generation {
+            return Err(ReleaseError::Stale(id));
+        }
+        // Release: like the drop of a packet handle
+        self.ring
+            .status(id.idx)
+            .transition(SlotState::PendingRelease, SlotState::Free)
+            .map_err(|err| match err.actual {
+                SlotState::Free | SlotState::Backend => {
+                    ReleaseError::DoubleRelease(id)
+                }
+                SlotState::User => ReleaseError::HandleAlive(id),
+                _ => ReleaseError::Stale(id),
+            })
+    }
+
+    /// Consumer statistics.
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+}
+
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
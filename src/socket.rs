@@ -0,0 +1,320 @@
+//! Nethuns-like sockets.
+
+use std::cell::{Cell, UnsafeCell};
+use std::time::{Duration, Instant};
+use std::{io, thread};
+
+use crate::backend::{Backend, MemoryBackend};
+use crate::error::{RecvError, ReleaseError, SendError};
+use crate::options::SocketOptions;
+use crate::packet::{PacketBatch, PacketId, RecvPacket, TxSlot};
+use crate::ring::{Ring, TxRing};
+use crate::stats::Stats;
+use crate::status::SlotState;
+
+
+/// Socket which emulates the behavior of a
+/// Nethuns socket in RX/TX mode.
+///
+/// The socket wraps an RX ring, which is responsible
+/// for receiving packets, and a TX ring, which is
+/// responsible for transmitting them. Packets are moved
+/// in and out of the rings by a [`Backend`], which is
+/// closed when the socket is dropped.
+///
+/// The rings are mutated through shared references, so the socket
+/// is `!Sync`. Wrap it in a [`SharedSocket`](crate::SharedSocket)
+/// to share it across threads. For the same reason, the socket must
+/// not be used by its own backend while it is running one of the
+/// backend methods: every method panics if the socket is re-entered.
+#[derive(Debug)]
+pub struct Socket<B: Backend = MemoryBackend> {
+    /// rx ring
+    rx: UnsafeCell<Ring>,
+    /// tx ring
+    tx: UnsafeCell<TxRing>,
+    /// packet source and sink
+    backend: UnsafeCell<B>,
+    /// Detects the socket being re-entered by its backend
+    guard: ReentrancyGuard,
+    /// How long `recv` waits for the next slot to be released
+    timeout: Duration,
+}
+
+impl Socket {
+    /// Create a new synthetic socket with the default options
+    pub fn new() -> Self {
+        Self::new_with(SocketOptions::default())
+    }
+
+    /// Create a new synthetic socket whose ring geometry
+    /// is described by `opts`
+    pub fn new_with(opts: SocketOptions) -> Self {
+        Self::with_backend(opts, MemoryBackend::new())
+            .expect("the synthetic backend cannot fail to open")
+    }
+}
+
+impl<B: Backend> Socket<B> {
+    /// Create a new socket over `backend`, whose ring geometry
+    /// is described by `opts`
+    pub fn with_backend(
+        opts: SocketOptions,
+        mut backend: B,
+    ) -> io::Result<Self> {
+        backend.open(&opts)?;
+        Ok(Socket {
+            rx: UnsafeCell::new(Ring::with_options(&opts)),
+            tx: UnsafeCell::new(TxRing::with_options(&opts)),
+            backend: UnsafeCell::new(backend),
+            guard: ReentrancyGuard::default(),
+            timeout: opts.timeout(),
+        })
+    }
+
+    /// Mutable reference to the backend.
+    pub fn backend_mut(&mut self) -> &mut B {
+        self.backend.get_mut()
+    }
+
+    /// Receive a packet, waiting up to the socket timeout
+    /// (see [`SocketOptions::timeout`]) for the next slot
+    /// to be released by packets owned by other threads
+    /// and for the backend to have a packet available.
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the next slot
+    /// of the ring is still held by a [`RecvPacket`], or if the
+    /// backend has no packet available, when the timeout expires.
+    pub fn recv(&self) -> Result<RecvPacket<'_>, RecvError> {
+        wait_for(self.timeout, || self.try_recv())
+    }
+
+    /// Receive a packet without waiting.
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the next slot
+    /// of the ring is still held by a [`RecvPacket`] or if
+    /// the backend has no packet available.
+    pub fn try_recv(&self) -> Result<RecvPacket<'_>, RecvError> {
+        let _entered = self.guard.enter();
+        // Call `recv` on the RX ring
+        // by exploiting the "inner mutability pattern"
+        unsafe { (*self.rx.get()).recv(&mut *self.backend.get()) }
+    }
+
+    /// Receive up to `max` packets in one pass and append them to
+    /// `batch`, waiting up to the socket timeout for the next slot to
+    /// be released.
+    ///
+    /// Returns the number of received packets, which is only zero
+    /// if `max` is zero.
+    pub fn recv_burst<'a>(
+        &'a self,
+        batch: &mut PacketBatch<'a>,
+        max: usize,
+    ) -> Result<usize, RecvError> {
+        wait_for(self.timeout, || self.try_recv_burst(batch, max))
+    }
+
+    /// Receive up to `max` packets in one pass and append them to
+    /// `batch`, without waiting.
+    ///
+    /// Fails with [`RecvError::WouldBlock`] if the next slot
+    /// of the ring is still held by a [`RecvPacket`] or if
+    /// the backend has no packet available.
+    pub fn try_recv_burst<'a>(
+        &'a self,
+        batch: &mut PacketBatch<'a>,
+        max: usize,
+    ) -> Result<usize, RecvError> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::try_recv`.
+        let packets = unsafe {
+            (*self.rx.get()).recv_burst(max, &mut *self.backend.get())?
+        };
+        let before = batch.len();
+        batch.extend(packets);
+        Ok(batch.len() - before)
+    }
+
+    /// Release the packet `id`, like `nethuns_rx_release`.
+    ///
+    /// The packet must have been detached from its handle with
+    /// [`RecvPacket::into_id`]: packets still held by a [`RecvPacket`]
+    /// are released when dropped. Packets can be released in any order,
+    /// but the ring only receives into a slot once it is released.
+    /// Ids of packets whose slot has been reused are rejected as stale.
+    pub fn release(&self, id: PacketId) -> Result<(), ReleaseError> {
+        let _entered = self.guard.enter();
+        // SAFETY: the socket is `!Sync` and not re-entered, so no
+        // `&mut Ring` can be alive while this shared borrow exists.
+        unsafe { (*self.rx.get()).release(id) }
+    }
+
+    /// Current state of the slot holding the packet `id`, or
+    /// [`ReleaseError::Stale`] if it now holds another packet.
+    pub fn lookup(&self, id: PacketId) -> Result<SlotState, ReleaseError> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::release`.
+        unsafe { (*self.rx.get()).lookup(id) }
+    }
+
+    /// Ids of the packets held by the user for at least `deadline`,
+    /// which may have been leaked.
+    ///
+    /// See [`Ring::held_longer_than`].
+    pub fn held_longer_than(&self, deadline: Duration) -> Vec<PacketId> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::release`.
+        unsafe { (*self.rx.get()).held_longer_than(deadline) }
+    }
+
+    /// Free the slot of the packet `id`, reported by
+    /// [`Socket::held_longer_than`], so that a ring stalled by a leaked
+    /// packet can receive again. Meant for long-running daemons which
+    /// prefer to recover from such a bug rather than stop receiving.
+    ///
+    /// # Safety
+    ///
+    /// The handle of the packet, if any, must have been leaked:
+    /// see [`Ring::force_reclaim`].
+    pub unsafe fn force_reclaim(
+        &self,
+        id: PacketId,
+    ) -> Result<(), ReleaseError> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::release`. The handle of the packet
+        // is never used again, as guaranteed by the caller.
+        unsafe { (*self.rx.get()).force_reclaim(id) }
+    }
+
+    /// Receive timeout.
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Copy `frame` into the TX ring and queue it for transmission.
+    ///
+    /// The frame is transmitted by the next [`Socket::flush`].
+    pub fn send(&self, frame: &[u8]) -> Result<(), SendError> {
+        let _entered = self.guard.enter();
+        // SAFETY: the socket is `!Sync` and not re-entered, and no
+        // reference into the TX ring outlives this call.
+        unsafe { (*self.tx.get()).send(frame) }
+    }
+
+    /// Reserve the next TX slot, so that a frame can be built
+    /// in place and then queued with [`TxSlot::commit`].
+    ///
+    /// Only one slot can be reserved at a time: until the returned
+    /// [`TxSlot`] is committed or dropped, further reservations and
+    /// sends fail with [`SendError::RingFull`].
+    pub fn reserve_tx(&self) -> Result<TxSlot<'_>, SendError> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::send`. The returned slot borrows
+        // the frame buffer, which the TX ring only touches again
+        // once the slot has been committed, aborted and flushed.
+        let (idx, packet) = unsafe { (*self.tx.get()).reserve()? };
+        Ok(TxSlot {
+            idx,
+            packet,
+            ring: &self.tx,
+            guard: &self.guard,
+        })
+    }
+
+    /// Transmit every queued frame through the backend.
+    ///
+    /// Returns the number of flushed frames, including the ones
+    /// which the backend failed to transmit (see [`Stats::tx_dropped`]).
+    pub fn flush(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::send`. The backend is only
+        // borrowed for the duration of the call.
+        unsafe { (*self.tx.get()).flush(&mut *self.backend.get()) }
+    }
+
+    /// Reclaim the TX slots whose frames have been transmitted.
+    ///
+    /// Returns the number of reclaimed slots.
+    pub fn complete_tx(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::send`.
+        unsafe { (*self.tx.get()).complete() }
+    }
+
+    /// Socket statistics, collected by the rings and the backend.
+    pub fn stats(&self) -> Stats {
+        let _entered = self.guard.enter();
+        // SAFETY: the socket is `!Sync` and not re-entered, so no
+        // `&mut Ring`, `&mut TxRing` or `&mut B` can be alive while
+        // these shared borrows exist.
+        unsafe {
+            (*self.rx.get()).stats()
+                + (*self.tx.get()).stats()
+                + (*self.backend.get()).stats()
+        }
+    }
+}
+
+impl<B: Backend> Drop for Socket<B> {
+    fn drop(&mut self) {
+        self.backend.get_mut().close();
+    }
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// Flag which detects a socket being re-entered while its rings or its
+/// backend are mutably borrowed, e.g. by a backend which holds a handle
+/// to its own socket and uses it from [`Backend::transmit`].
+#[derive(Debug, Default)]
+pub(crate) struct ReentrancyGuard {
+    busy: Cell<bool>,
+}
+
+impl ReentrancyGuard {
+    /// Mark the socket as in use until the returned token is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the socket is already in use.
+    pub(crate) fn enter(&self) -> Entered<'_> {
+        assert!(
+            !self.busy.replace(true),
+            "socket re-entered while in use, e.g. by its own backend"
+        );
+        Entered(&self.busy)
+    }
+}
+
+/// Token returned by [`ReentrancyGuard::enter`].
+pub(crate) struct Entered<'a>(&'a Cell<bool>);
+
+impl Drop for Entered<'_> {
+    fn drop(&mut self) {
+        self.0.set(false);
+    }
+}
+
+
+/// Call `f` until it stops failing with [`RecvError::WouldBlock`]
+/// or until `timeout` expires.
+pub(crate) fn wait_for<T>(
+    timeout: Duration,
+    mut f: impl FnMut() -> Result<T, RecvError>,
+) -> Result<T, RecvError> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match f() {
+            Err(RecvError::WouldBlock) if Instant::now() < deadline => {
+                thread::yield_now()
+            }
+            res => return res,
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Socket statistics, modeled on Nethuns' `nethuns_stat`.
+
+use std::ops::Add;
+
+
+/// Counters collected by a [`Ring`](crate::Ring), a [`TxRing`](crate::TxRing)
+/// and a [`Backend`](crate::Backend).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Packets handed out to the user
+    pub rx_packets: u64,
+    /// Receive attempts which found the next slot still in use
+    pub rx_ring_full: u64,
+    /// Packets transmitted by a flush
+    pub tx_packets: u64,
+    /// Send attempts which found the next slot not completed yet
+    pub tx_ring_full: u64,
+    /// Packets dropped by the backend before reaching a slot
+    pub rx_dropped: u64,
+    /// Flushed frames which the backend failed to transmit
+    pub tx_dropped: u64,
+}
+
+impl Add for Stats {
+    type Output = Stats;
+
+    fn add(self, other: Stats) -> Stats {
+        Stats {
+            rx_packets: self.rx_packets + other.rx_packets,
+            rx_ring_full: self.rx_ring_full + other.rx_ring_full,
+            tx_packets: self.tx_packets + other.tx_packets,
+            tx_ring_full: self.tx_ring_full + other.tx_ring_full,
+            rx_dropped: self.rx_dropped + other.rx_dropped,
+            tx_dropped: self.tx_dropped + other.tx_dropped,
+        }
+    }
+}
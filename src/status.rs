@@ -0,0 +1,163 @@
+//! Slot lifecycle, shared by RX and TX rings.
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::error::IllegalTransition;
+
+
+/// State of a ring slot.
+///
+/// RX slots cycle through
+/// `Free -> Backend -> User -> Free`:
+/// the backend fills the slot, which is then held by a
+/// [`RecvPacket`](crate::RecvPacket) until it is dropped.
+/// If the backend fails to fill the slot, it goes back from
+/// `Backend` to `Free`. A packet can also be detached from its handle
+/// ([`RecvPacket::into_id`](crate::RecvPacket::into_id)), which moves
+/// its slot from `User` to `PendingRelease` until the packet is
+/// released by id ([`Socket::release`](crate::Socket::release)).
+///
+/// TX slots cycle through
+/// `Free -> User -> InFlight -> Backend -> PendingRelease -> Free`:
+/// the user fills the slot and queues it, a flush hands it over to the
+/// backend, which transmits it, and the completion step reclaims it.
+/// A reserved TX slot can also go back from `User` to `Free` if its
+/// frame is not committed.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotState {
+    /// The slot can be claimed by the ring
+    Free = 0,
+    /// The slot is owned by the kernel/backend
+    Backend = 1,
+    /// The slot is held by the user
+    User = 2,
+    /// The slot holds a TX frame waiting to be flushed
+    InFlight = 3,
+    /// The slot is done with, but has not been reclaimed yet
+    PendingRelease = 4,
+}
+
+impl SlotState {
+    /// Check whether a slot can move from `self` to `next`.
+    pub fn can_transition_to(self, next: SlotState) -> bool {
+        use SlotState::*;
+        matches!(
+            (self, next),
+            (Free, Backend)
+                | (Free, User)
+                | (Backend, Free)
+                | (Backend, User)
+                | (Backend, PendingRelease)
+                | (User, Free)
+                | (User, InFlight)
+                | (User, PendingRelease)
+                | (InFlight, Backend)
+                | (PendingRelease, Free)
+        )
+    }
+
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => SlotState::Free,
+            1 => SlotState::Backend,
+            2 => SlotState::User,
+            3 => SlotState::InFlight,
+            4 => SlotState::PendingRelease,
+            _ => unreachable!("invalid slot state {value}"),
+        }
+    }
+}
+
+impl Display for SlotState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SlotState::Free => "FREE",
+            SlotState::Backend => "BACKEND",
+            SlotState::User => "USER",
+            SlotState::InFlight => "IN-FLIGHT",
+            SlotState::PendingRelease => "PENDING-RELEASE",
+        };
+        f.write_str(name)
+    }
+}
+
+
+/// Atomic [`SlotState`] of a ring slot.
+///
+/// Every state change goes through [`SlotStatus::transition`],
+/// which only succeeds if the slot is in the expected state and
+/// the transition is allowed by [`SlotState::can_transition_to`].
+/// Successful transitions have release semantics, so that the next
+/// owner of the slot observes every write made by the previous one.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct SlotStatus(AtomicU8);
+
+impl SlotStatus {
+    /// Create a new status in state `state`.
+    pub fn new(state: SlotState) -> Self {
+        SlotStatus(AtomicU8::new(state as u8))
+    }
+
+    /// View the status byte at `ptr`, e.g. in memory shared with
+    /// another process.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for `'a`, hold a valid [`SlotState`], and
+    /// only ever be accessed atomically during `'a`.
+    pub(crate) unsafe fn from_ptr<'a>(ptr: *mut u8) -> &'a SlotStatus {
+        // SAFETY: `SlotStatus` has the layout of an `AtomicU8`,
+        // which has the layout of a `u8`.
+        unsafe { &*ptr.cast::<SlotStatus>() }
+    }
+
+    /// Current state of the slot.
+    pub fn load(&self) -> SlotState {
+        SlotState::from_u8(self.0.load(Ordering::Acquire))
+    }
+
+    /// Move the slot from state `from` to state `to`.
+    ///
+    /// Fails, leaving the slot untouched, if the transition is not
+    /// allowed or if the slot is not in state `from`.
+    pub fn transition(
+        &self,
+        from: SlotState,
+        to: SlotState,
+    ) -> Result<(), IllegalTransition> {
+        if !from.can_transition_to(to) {
+            return Err(IllegalTransition {
+                expected: from,
+                actual: from,
+                to,
+            });
+        }
+        self.0
+            .compare_exchange(
+                from as u8,
+                to as u8,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .map(|_| ())
+            .map_err(|actual| IllegalTransition {
+                expected: from,
+                actual: SlotState::from_u8(actual),
+                to,
+            })
+    }
+
+    /// Move the slot from state `from` to state `to`,
+    /// for transitions which can only fail because of a bug.
+    ///
+    /// Illegal transitions panic in debug builds
+    /// and leave the slot untouched in release builds.
+    pub(crate) fn advance(&self, from: SlotState, to: SlotState) {
+        if let Err(err) = self.transition(from, to) {
+            debug_assert!(false, "{err}");
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Sinks which save received packets to capture files.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::backend::{PCAP_MAGIC_NSEC, PCAP_MAGIC_USEC};
+use crate::options::MAX_FRAME_SIZE;
+use crate::packet::{PacketHeader, RecvPacket};
+
+
+/// Link-layer header type of Ethernet frames.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+/// pcapng block types.
+pub(crate) const PCAPNG_SHB: u32 = 0x0a0d_0d0a;
+pub(crate) const PCAPNG_IDB: u32 = 0x0000_0001;
+pub(crate) const PCAPNG_EPB: u32 = 0x0000_0006;
+/// pcapng byte-order magic.
+pub(crate) const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+
+/// Format of a capture file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PcapFormat {
+    /// Classic pcap with microsecond timestamps
+    #[default]
+    Pcap,
+    /// Classic pcap with nanosecond timestamps
+    PcapNanos,
+    /// pcapng with a single interface and nanosecond timestamps
+    PcapNg,
+}
+
+impl PcapFormat {
+    /// Usual file extension, including the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            PcapFormat::Pcap | PcapFormat::PcapNanos => ".pcap",
+            PcapFormat::PcapNg => ".pcapng",
+        }
+    }
+}
+
+
+/// Writer of capture files.
+///
+/// Packets are written straight from the slot they are borrowed from:
+/// the record header is built on the stack and the payload is passed
+/// as is to the underlying writer.
+///
+/// ```
+/// use rust_nethuns_miri::{PcapFormat, PcapWriter, Socket};
+///
+/// let socket = Socket::new();
+/// let mut writer = PcapWriter::new(Vec::new(), PcapFormat::Pcap).unwrap();
+/// writer.write_packet(&socket.recv().unwrap()).unwrap();
+/// assert_eq!(writer.bytes_written(), 24 + 16 + 5);
+/// ```
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    writer: W,
+    format: PcapFormat,
+    linktype: u32,
+    snaplen: u32,
+    bytes_written: u64,
+    packets_written: u64,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Write the file header of an Ethernet capture to `writer`.
+    pub fn new(writer: W, format: PcapFormat) -> io::Result<Self> {
+        Self::with_linktype(writer, format, LINKTYPE_ETHERNET)
+    }
+
+    /// Write the file header of a capture of `linktype` packets to `writer`.
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] if `linktype` does not
+    /// fit the 16 bits of a pcapng interface.
+    pub fn with_linktype(
+        writer: W,
+        format: PcapFormat,
+        linktype: u32,
+    ) -> io::Result<Self> {
+        if format == PcapFormat::PcapNg && u16::try_from(linktype).is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("link type {linktype} does not fit a pcapng interface"),
+            ));
+        }
+        let mut pcap = PcapWriter {
+            writer,
+            format,
+            linktype,
+            snaplen: MAX_FRAME_SIZE as u32,
+            bytes_written: 0,
+            packets_written: 0,
+        };
+
+        match format {
+            PcapFormat::Pcap | PcapFormat::PcapNanos => {
+                let magic = if format == PcapFormat::Pcap {
+                    PCAP_MAGIC_USEC
+                } else {
+                    PCAP_MAGIC_NSEC
+                };
+                let mut header = [0; 24];
+                header[0..4].copy_from_slice(&magic.to_le_bytes());
+                header[4..6].copy_from_slice(&2u16.to_le_bytes());
+                header[6..8].copy_from_slice(&4u16.to_le_bytes());
+                header[16..20].copy_from_slice(&pcap.snaplen.to_le_bytes());
+                header[20..24].copy_from_slice(&linktype.to_le_bytes());
+                pcap.write_all(&header)?;
+            }
+            PcapFormat::PcapNg => {
+                // Section Header Block, with unknown section length
+                let mut shb = [0; 28];
+                shb[0..4].copy_from_slice(&PCAPNG_SHB.to_le_bytes());
+                shb[4..8].copy_from_slice(&28u32.to_le_bytes());
+                shb[8..12]
+                    .copy_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+                shb[12..14].copy_from_slice(&1u16.to_le_bytes());
+                shb[16..24].copy_from_slice(&(-1i64).to_le_bytes());
+                shb[24..28].copy_from_slice(&28u32.to_le_bytes());
+                pcap.write_all(&shb)?;
+
+                // Interface Description Block, with nanosecond resolution
+                // (option `if_tsresol` = 9) and the end of options
+                let mut idb = [0; 32];
+                idb[0..4].copy_from_slice(&PCAPNG_IDB.to_le_bytes());
+                idb[4..8].copy_from_slice(&32u32.to_le_bytes());
+                // Checked above
+                idb[8..10].copy_from_slice(&(linktype as u16).to_le_bytes());
+                idb[12..16].copy_from_slice(&pcap.snaplen.to_le_bytes());
+                idb[16..18].copy_from_slice(&9u16.to_le_bytes());
+                idb[18..20].copy_from_slice(&1u16.to_le_bytes());
+                idb[20] = 9;
+                idb[28..32].copy_from_slice(&32u32.to_le_bytes());
+                pcap.write_all(&idb)?;
+            }
+        }
+
+        Ok(pcap)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)?;
+        self.bytes_written += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Size of the record holding a `caplen`-byte packet,
+    /// after truncation to the snapshot length.
+    pub fn record_size(&self, caplen: usize) -> u64 {
+        let caplen = caplen.min(self.snaplen as usize);
+        match self.format {
+            PcapFormat::Pcap | PcapFormat::PcapNanos => 16 + caplen as u64,
+            PcapFormat::PcapNg => 32 + padded(caplen) as u64,
+        }
+    }
+
+    /// Write `packet`, which may still be held by the user.
+    pub fn write_packet(&mut self, packet: &RecvPacket) -> io::Result<()> {
+        self.write(packet.header(), packet.packet())
+    }
+
+    /// Write the packet described by `header`, whose stored bytes
+    /// are `payload`.
+    ///
+    /// Payloads longer than the snapshot length declared in the file
+    /// header are truncated, and their original length is kept.
+    pub fn write(
+        &mut self,
+        header: &PacketHeader,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let payload = &payload[..payload.len().min(self.snaplen as usize)];
+        let caplen = u32::try_from(payload.len()).unwrap();
+        let len = header.len.max(payload.len());
+        let len = u32::try_from(len).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("packet length {len} does not fit a record"),
+            )
+        })?;
+
+        match self.format {
+            PcapFormat::Pcap | PcapFormat::PcapNanos => {
+                let frac = if self.format == PcapFormat::Pcap {
+                    header.timestamp.subsec_micros()
+                } else {
+                    header.timestamp.subsec_nanos()
+                };
+                let mut record = [0; 16];
+                record[0..4].copy_from_slice(
+                    &(header.timestamp.as_secs() as u32).to_le_bytes(),
+                );
+                record[4..8].copy_from_slice(&frac.to_le_bytes());
+                record[8..12].copy_from_slice(&caplen.to_le_bytes());
+                record[12..16].copy_from_slice(&len.to_le_bytes());
+                self.write_all(&record)?;
+                self.write_all(payload)?;
+            }
+            PcapFormat::PcapNg => {
+                // Enhanced Packet Block, without options
+                let total = self.record_size(payload.len()) as u32;
+                let ts = header.timestamp.as_nanos() as u64;
+                let mut block = [0; 28];
+                block[0..4].copy_from_slice(&PCAPNG_EPB.to_le_bytes());
+                block[4..8].copy_from_slice(&total.to_le_bytes());
+                block[12..16]
+                    .copy_from_slice(&((ts >> 32) as u32).to_le_bytes());
+                block[16..20].copy_from_slice(&(ts as u32).to_le_bytes());
+                block[20..24].copy_from_slice(&caplen.to_le_bytes());
+                block[24..28].copy_from_slice(&len.to_le_bytes());
+                self.write_all(&block)?;
+                self.write_all(payload)?;
+                let padding = padded(payload.len()) - payload.len();
+                self.write_all(&[0; 3][..padding])?;
+                self.write_all(&total.to_le_bytes())?;
+            }
+        }
+
+        self.packets_written += 1;
+        Ok(())
+    }
+
+    /// Capture format.
+    pub fn format(&self) -> PcapFormat {
+        self.format
+    }
+
+    /// Link-layer header type declared in the file header.
+    pub fn linktype(&self) -> u32 {
+        self.linktype
+    }
+
+    /// Snapshot length declared in the file header.
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+
+    /// Bytes written so far, including the file header.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Packets written so far.
+    pub fn packets_written(&self) -> u64 {
+        self.packets_written
+    }
+
+    /// Flush the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    /// Unwrap the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+
+/// `len` rounded up to a multiple of 4, as pcapng blocks require.
+pub(crate) fn padded(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+
+/// When a [`RotatingPcapWriter`] starts a new file.
+///
+/// A file is never rotated before holding at least one packet.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rotation {
+    /// Maximum size in bytes of a file
+    pub max_bytes: Option<u64>,
+    /// Maximum time between the first and the last packet of a file,
+    /// measured on packet timestamps
+    pub max_duration: Option<Duration>,
+}
+
+
+/// Writer of capture files, which moves on to a new file
+/// according to a [`Rotation`] policy.
+pub struct RotatingPcapWriter<W: Write> {
+    current: PcapWriter<W>,
+    format: PcapFormat,
+    linktype: u32,
+    rotation: Rotation,
+    /// Opens the `n`-th file
+    open: Box<dyn FnMut(u32) -> io::Result<W>>,
+    files: u32,
+    /// Timestamp of the first packet of the current file
+    first_timestamp: Option<Duration>,
+}
+
+impl RotatingPcapWriter<BufWriter<File>> {
+    /// Write captures of `linktype` packets to the files
+    /// `<stem>.0<ext>`, `<stem>.1<ext>`, ... next to `path`,
+    /// where `<ext>` depends on `format`.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: PcapFormat,
+        linktype: u32,
+        rotation: Rotation,
+    ) -> io::Result<Self> {
+        let path: PathBuf = path.as_ref().to_owned();
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Self::new(format, linktype, rotation, move |n| {
+            let name = format!("{stem}.{n}{}", format.extension());
+            File::create(path.with_file_name(name)).map(BufWriter::new)
+        })
+    }
+}
+
+impl<W: Write> RotatingPcapWriter<W> {
+    /// Write captures of `linktype` packets to the files returned by
+    /// `open`, which is called with the index of the file to be opened
+    /// (starting from 0).
+    pub fn new(
+        format: PcapFormat,
+        linktype: u32,
+        rotation: Rotation,
+        open: impl FnMut(u32) -> io::Result<W> + 'static,
+    ) -> io::Result<Self> {
+        let mut open: Box<dyn FnMut(u32) -> io::Result<W>> = Box::new(open);
+        let current = PcapWriter::with_linktype(open(0)?, format, linktype)?;
+        Ok(RotatingPcapWriter {
+            current,
+            format,
+            linktype,
+            rotation,
+            open,
+            files: 1,
+            first_timestamp: None,
+        })
+    }
+
+    /// Write `packet`, rotating the file first if required.
+    pub fn write_packet(&mut self, packet: &RecvPacket) -> io::Result<()> {
+        self.write(packet.header(), packet.packet())
+    }
+
+    /// Write the packet described by `header`, whose stored bytes
+    /// are `payload`, rotating the file first if required.
+    pub fn write(
+        &mut self,
+        header: &PacketHeader,
+        payload: &[u8],
+    ) -> io::Result<()> {
+        if self.must_rotate(header, payload.len()) {
+            self.rotate()?;
+        }
+        self.current.write(header, payload)?;
+        self.first_timestamp.get_or_insert(header.timestamp);
+        Ok(())
+    }
+
+    fn must_rotate(&self, header: &PacketHeader, caplen: usize) -> bool {
+        let Some(first) = self.first_timestamp else {
+            return false;
+        };
+        let too_large = self.rotation.max_bytes.is_some_and(|max| {
+            self.current.bytes_written() + self.current.record_size(caplen)
+                > max
+        });
+        let too_long = self
+            .rotation
+            .max_duration
+            .is_some_and(|max| header.timestamp.saturating_sub(first) >= max);
+        too_large || too_long
+    }
+
+    /// Close the current file and start a new one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.current.flush()?;
+        let writer = (self.open)(self.files)?;
+        self.current =
+            PcapWriter::with_linktype(writer, self.format, self.linktype)?;
+        self.files += 1;
+        self.first_timestamp = None;
+        Ok(())
+    }
+
+    /// Number of files opened so far.
+    pub fn files(&self) -> u32 {
+        self.files
+    }
+
+    /// Writer of the current file.
+    pub fn current(&self) -> &PcapWriter<W> {
+        &self.current
+    }
+
+    /// Flush the current file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.current.flush()
+    }
+}
+
+impl<W: Write + fmt::Debug> fmt::Debug for RotatingPcapWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RotatingPcapWriter")
+            .field("current", &self.current)
+            .field("rotation", &self.rotation)
+            .field("files", &self.files)
+            .finish_non_exhaustive()
+    }
+}
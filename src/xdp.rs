@@ -0,0 +1,527 @@
+//! AF_XDP-style sockets, whose frames cycle through a UMEM
+//! and four rings.
+
+use std::cell::UnsafeCell;
+use std::fmt::{self, Display};
+use std::{io, mem, ptr};
+
+use crate::arena::FrameArena;
+use crate::backend::{Backend, MemoryBackend};
+use crate::error::RecvError;
+use crate::options::SocketOptions;
+use crate::packet::PacketHeader;
+use crate::socket::ReentrancyGuard;
+use crate::stats::Stats;
+use crate::status::{SlotState, SlotStatus};
+
+
+/// Descriptor of a frame in the RX and TX rings.
+#[derive(Debug, Clone, Copy, Default)]
+struct XdpDesc {
+    /// Offset of the frame in the UMEM
+    addr: u64,
+    /// Length of the packet stored in the frame
+    len: usize,
+}
+
+
+/// Single-producer single-consumer ring, with free-running
+/// producer and consumer indexes like the AF_XDP rings.
+#[derive(Debug)]
+struct XdpRing<T> {
+    entries: Box<[T]>,
+    producer: u32,
+    consumer: u32,
+}
+
+impl<T: Copy + Default> XdpRing<T> {
+    /// Create an empty ring of `size` entries, a power of two.
+    fn new(size: usize) -> Self {
+        debug_assert!(size.is_power_of_two());
+        XdpRing {
+            entries: vec![T::default(); size].into_boxed_slice(),
+            producer: 0,
+            consumer: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.producer.wrapping_sub(self.consumer) as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len() == self.entries.len()
+    }
+
+    fn mask(&self, idx: u32) -> usize {
+        idx as usize & (self.entries.len() - 1)
+    }
+
+    /// Append `entry`, unless the ring is full.
+    fn produce(&mut self, entry: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let idx = self.mask(self.producer);
+        self.entries[idx] = entry;
+        self.producer = self.producer.wrapping_add(1);
+        true
+    }
+
+    /// Oldest entry, which is not consumed.
+    fn peek(&self) -> Option<T> {
+        (!self.is_empty()).then(|| self.entries[self.mask(self.consumer)])
+    }
+
+    /// Remove the oldest entry.
+    fn consume(&mut self) -> Option<T> {
+        let entry = self.peek()?;
+        self.consumer = self.consumer.wrapping_add(1);
+        Some(entry)
+    }
+}
+
+
+/// Rings of an [`XdpSocket`], mutated through the socket.
+#[derive(Debug)]
+struct Rings {
+    /// Free frames given to the kernel for reception
+    fill: XdpRing<u64>,
+    /// Frames received by the kernel
+    rx: XdpRing<XdpDesc>,
+    /// Frames queued for transmission
+    tx: XdpRing<XdpDesc>,
+    /// Frames transmitted by the kernel
+    completion: XdpRing<u64>,
+    /// Next frame to look at when searching for free frames
+    cursor: usize,
+    stats: Stats,
+}
+
+
+/// Socket which emulates an AF_XDP socket bound to its own UMEM.
+///
+/// The UMEM is a [`FrameArena`] whose frames are addressed by their
+/// offset, and which cycle through four rings:
+///
+/// - free frames are given to the kernel through the *fill* ring
+///   ([`XdpSocket::refill`]);
+/// - the kernel stores received packets into them, and hands them back
+///   through the *RX* ring ([`XdpSocket::recv`]);
+/// - frames to transmit, either received or allocated with
+///   [`XdpSocket::alloc_tx`], are queued on the *TX* ring
+///   ([`XdpSocket::send`]), and then transmitted by the kernel
+///   ([`XdpSocket::flush`]);
+/// - transmitted frames are handed back through the *completion* ring
+///   ([`XdpSocket::complete`]).
+///
+/// Here the kernel side is played by a [`Backend`]. The owner of each
+/// frame is tracked by a [`SlotStatus`]: a free frame is
+/// [`SlotState::Free`], a frame on the fill or RX ring is
+/// [`SlotState::Backend`], a frame held through an [`XdpFrame`] is
+/// [`SlotState::User`], a frame on the TX ring is
+/// [`SlotState::InFlight`] and a frame on the completion ring is
+/// [`SlotState::PendingRelease`].
+///
+/// The backend must not use the socket which owns it: every method
+/// panics if the socket is re-entered.
+///
+/// ```
+/// use rust_nethuns_miri::XdpSocket;
+///
+/// let socket = XdpSocket::new();
+/// socket.refill();
+/// let mut frame = socket.recv().unwrap();
+/// frame.data_mut()[0] = 42;
+/// socket.send(frame).unwrap();
+/// assert_eq!(socket.flush(), 1);
+/// assert_eq!(socket.complete(), 1);
+/// ```
+#[derive(Debug)]
+pub struct XdpSocket<B: Backend = MemoryBackend> {
+    umem: FrameArena,
+    status: Box<[SlotStatus]>,
+    rings: UnsafeCell<Rings>,
+    backend: UnsafeCell<B>,
+    /// Detects the socket being re-entered by its backend
+    guard: ReentrancyGuard,
+}
+
+impl XdpSocket {
+    /// Create a new synthetic socket with the default options.
+    pub fn new() -> Self {
+        Self::new_with(SocketOptions::default())
+    }
+
+    /// Create a new synthetic socket whose UMEM
+    /// is described by `opts`.
+    pub fn new_with(opts: SocketOptions) -> Self {
+        Self::with_backend(opts, MemoryBackend::new())
+            .expect("the synthetic backend cannot fail to open")
+    }
+}
+
+impl<B: Backend> XdpSocket<B> {
+    /// Create a new socket over `backend`, with a UMEM of
+    /// `opts.num_slots()` frames of `opts.frame_size()` bytes each.
+    /// Every ring can hold all the frames.
+    pub fn with_backend(
+        opts: SocketOptions,
+        mut backend: B,
+    ) -> io::Result<Self> {
+        backend.open(&opts)?;
+        let num_frames = opts.num_slots();
+        let size = num_frames.next_power_of_two();
+        Ok(XdpSocket {
+            umem: FrameArena::new(
+                num_frames,
+                opts.frame_size(),
+                opts.frame_align(),
+            ),
+            status: (0..num_frames)
+                .map(|_| SlotStatus::new(SlotState::Free))
+                .collect(),
+            rings: UnsafeCell::new(Rings {
+                fill: XdpRing::new(size),
+                rx: XdpRing::new(size),
+                tx: XdpRing::new(size),
+                completion: XdpRing::new(size),
+                cursor: 0,
+                stats: Stats::default(),
+            }),
+            backend: UnsafeCell::new(backend),
+            guard: ReentrancyGuard::default(),
+        })
+    }
+
+    /// The UMEM.
+    pub fn umem(&self) -> &FrameArena {
+        &self.umem
+    }
+
+    /// Current state of the frame at `addr`, or `None` if no frame
+    /// of the UMEM starts at `addr`.
+    pub fn frame_state(&self, addr: u64) -> Option<SlotState> {
+        self.index(addr).map(|idx| self.status[idx].load())
+    }
+
+    /// Number of frames on the fill ring.
+    pub fn fill_len(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: the socket is `!Sync` and not re-entered, and no
+        // reference into the rings outlives any method call.
+        unsafe { (*self.rings.get()).fill.len() }
+    }
+
+    /// Number of frames on the completion ring.
+    pub fn completion_len(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`.
+        unsafe { (*self.rings.get()).completion.len() }
+    }
+
+    /// Give every free frame to the kernel through the fill ring.
+    ///
+    /// Returns the number of frames added to the fill ring.
+    pub fn refill(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`.
+        let rings = unsafe { &mut *self.rings.get() };
+        let mut count = 0;
+        while !rings.fill.is_full() {
+            let Some(idx) = self.next_free(rings) else {
+                break;
+            };
+            self.status[idx].advance(SlotState::Free, SlotState::Backend);
+            rings.fill.produce(self.addr(idx));
+            count += 1;
+        }
+        count
+    }
+
+    /// Receive the next packet.
+    ///
+    /// If the RX ring is empty, the backend first fills as many frames
+    /// from the fill ring as possible. Fails with
+    /// [`RecvError::WouldBlock`] if the fill ring is empty too, or with
+    /// the error returned by [`Backend::fill`].
+    pub fn recv(&self) -> Result<XdpFrame<'_>, RecvError> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`. The backend is only
+        // borrowed for the duration of the call.
+        let (rings, backend) =
+            unsafe { (&mut *self.rings.get(), &mut *self.backend.get()) };
+        if rings.rx.is_empty() {
+            if rings.fill.is_empty() {
+                rings.stats.rx_ring_full += 1;
+                return Err(RecvError::WouldBlock);
+            }
+            self.kernel_rx(rings, backend)?;
+        }
+
+        let desc = rings.rx.consume().expect("the RX ring is not empty");
+        let idx = self.ring_index(desc.addr);
+        self.status[idx].advance(SlotState::Backend, SlotState::User);
+        rings.stats.rx_packets += 1;
+        Ok(self.frame(idx, desc.len))
+    }
+
+    /// Move frames from the fill ring to the RX ring,
+    /// filling them through `backend`.
+    ///
+    /// The error of the backend is only returned if no frame was filled.
+    fn kernel_rx(
+        &self,
+        rings: &mut Rings,
+        backend: &mut B,
+    ) -> Result<(), RecvError> {
+        let mut filled = 0;
+        while !rings.rx.is_full() {
+            let Some(addr) = rings.fill.peek() else {
+                break;
+            };
+            let idx = self.ring_index(addr);
+            let mut header = PacketHeader::default();
+            // SAFETY: the frame is on the fill ring,
+            // so no handle borrows it.
+            let frame = unsafe { self.umem.frame_mut(idx) };
+            match backend.fill(frame, &mut header) {
+                Ok(()) => {}
+                Err(err) if filled == 0 => return Err(err),
+                Err(_) => break,
+            }
+            rings.fill.consume();
+            rings.rx.produce(XdpDesc {
+                addr,
+                len: header.caplen.min(frame.len()),
+            });
+            filled += 1;
+        }
+        Ok(())
+    }
+
+    /// Allocate a free frame to build a packet in.
+    ///
+    /// The frame is as long as the whole UMEM frame, see
+    /// [`XdpFrame::set_len`]. Returns `None` if no frame is free.
+    pub fn alloc_tx(&self) -> Option<XdpFrame<'_>> {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`.
+        let rings = unsafe { &mut *self.rings.get() };
+        let idx = self.next_free(rings)?;
+        self.status[idx].advance(SlotState::Free, SlotState::User);
+        Some(self.frame(idx, self.umem.frame_size()))
+    }
+
+    /// Queue `frame` on the TX ring.
+    ///
+    /// Gives the frame back if the TX ring is full, or if the frame
+    /// belongs to another socket.
+    pub fn send<'a>(&'a self, frame: XdpFrame<'a>) -> Result<(), XdpFrame<'a>> {
+        let Some(idx) = self
+            .index(frame.addr)
+            .filter(|&idx| ptr::eq(frame.status, &self.status[idx]))
+        else {
+            return Err(frame);
+        };
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`.
+        let rings = unsafe { &mut *self.rings.get() };
+        if !rings.tx.produce(XdpDesc {
+            addr: frame.addr,
+            len: frame.len,
+        }) {
+            rings.stats.tx_ring_full += 1;
+            return Err(frame);
+        }
+        self.status[idx].advance(SlotState::User, SlotState::InFlight);
+        // The frame is now owned by the TX ring
+        mem::forget(frame);
+        Ok(())
+    }
+
+    /// Transmit every frame on the TX ring through the backend,
+    /// and move it to the completion ring.
+    ///
+    /// Frames which the backend fails to transmit are dropped, and
+    /// completed like the transmitted ones. Returns the number of
+    /// flushed frames.
+    pub fn flush(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::recv`.
+        let (rings, backend) =
+            unsafe { (&mut *self.rings.get(), &mut *self.backend.get()) };
+        let mut count = 0;
+        while !rings.completion.is_full() {
+            let Some(desc) = rings.tx.consume() else {
+                break;
+            };
+            let idx = self.ring_index(desc.addr);
+            let status = &self.status[idx];
+            status.advance(SlotState::InFlight, SlotState::Backend);
+            // SAFETY: the frame is held by the backend,
+            // so nothing mutates it.
+            let frame = unsafe { self.umem.frame(idx) };
+            match backend.transmit(&frame[..desc.len]) {
+                Ok(()) => rings.stats.tx_packets += 1,
+                Err(_) => rings.stats.tx_dropped += 1,
+            }
+            status.advance(SlotState::Backend, SlotState::PendingRelease);
+            rings.completion.produce(desc.addr);
+            count += 1;
+        }
+        count
+    }
+
+    /// Free every frame on the completion ring.
+    ///
+    /// Returns the number of freed frames.
+    pub fn complete(&self) -> usize {
+        let _entered = self.guard.enter();
+        // SAFETY: see `XdpSocket::fill_len`.
+        let rings = unsafe { &mut *self.rings.get() };
+        let mut count = 0;
+        while let Some(addr) = rings.completion.consume() {
+            self.status[self.ring_index(addr)]
+                .advance(SlotState::PendingRelease, SlotState::Free);
+            count += 1;
+        }
+        count
+    }
+
+    /// Socket statistics, collected by the rings and the backend.
+    pub fn stats(&self) -> Stats {
+        let _entered = self.guard.enter();
+        // SAFETY: see `Socket::stats`.
+        unsafe { (*self.rings.get()).stats + (*self.backend.get()).stats() }
+    }
+
+    /// Next free frame, starting from the ring cursor.
+    fn next_free(&self, rings: &mut Rings) -> Option<usize> {
+        let len = self.status.len();
+        let idx = (0..len)
+            .map(|k| (rings.cursor + k) % len)
+            .find(|&idx| self.status[idx].load() == SlotState::Free)?;
+        rings.cursor = (idx + 1) % len;
+        Some(idx)
+    }
+
+    fn addr(&self, idx: usize) -> u64 {
+        (idx * self.umem.stride()) as u64
+    }
+
+    /// Index of the frame which starts at `addr`, if any.
+    fn index(&self, addr: u64) -> Option<usize> {
+        let addr = usize::try_from(addr).ok()?;
+        let stride = self.umem.stride();
+        let idx = addr / stride;
+        (addr % stride == 0 && idx < self.status.len()).then_some(idx)
+    }
+
+    /// Index of the frame at `addr`, taken from one of the rings.
+    fn ring_index(&self, addr: u64) -> usize {
+        self.index(addr).expect("the rings only hold UMEM frames")
+    }
+
+    /// Handle for the frame `idx`, which is held by the user.
+    fn frame(&self, idx: usize, len: usize) -> XdpFrame<'_> {
+        XdpFrame {
+            addr: self.addr(idx),
+            len,
+            status: &self.status[idx],
+            // SAFETY: the frame is held by the user, and only the
+            // returned handle borrows it until it is dropped or sent.
+            data: unsafe { self.umem.frame_mut(idx) },
+        }
+    }
+}
+
+impl<B: Backend> Drop for XdpSocket<B> {
+    fn drop(&mut self) {
+        self.backend.get_mut().close();
+    }
+}
+
+impl Default for XdpSocket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// UMEM frame held by the user, the AF_XDP counterpart of a
+/// [`RecvPacket`](crate::RecvPacket).
+///
+/// The frame is either queued for transmission with
+/// [`XdpSocket::send`], or freed when dropped.
+#[derive(Debug)]
+pub struct XdpFrame<'a> {
+    addr: u64,
+    len: usize,
+    status: &'a SlotStatus,
+    data: &'a mut [u8],
+}
+
+impl XdpFrame<'_> {
+    /// Offset of the frame in the UMEM.
+    pub fn addr(&self) -> u64 {
+        self.addr
+    }
+
+    /// Length of the packet.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Check whether the packet is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Packet bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Mutable packet bytes.
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data[..self.len]
+    }
+
+    /// Set the length of the packet, up to the UMEM frame size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` exceeds the UMEM frame size.
+    pub fn set_len(&mut self, len: usize) {
+        assert!(
+            len <= self.data.len(),
+            "packet length {len} exceeds the frame size {}",
+            self.data.len()
+        );
+        self.len = len;
+    }
+}
+
+impl Display for XdpFrame<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "addr: {:?}, status: {}, packet: {:?}",
+            self.addr,
+            self.status.load(),
+            self.data()
+        )
+    }
+}
+
+impl Drop for XdpFrame<'_> {
+    fn drop(&mut self) {
+        // Give the frame back to the UMEM, to be refilled
+        self.status.advance(SlotState::User, SlotState::Free);
+    }
+}
@@ -0,0 +1,168 @@
+//! Tests of sockets over custom backends.
+
+mod common;
+
+use std::cell::OnceCell;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+
+use rust_nethuns_miri::{Backend, PacketHeader, RecvError, Socket, Stats};
+
+
+/// Backend which yields a fixed list of packets
+/// and records the transmitted frames.
+#[derive(Default)]
+struct ListBackend {
+    packets: Vec<Vec<u8>>,
+    transmitted: Vec<Vec<u8>>,
+    dropped: u64,
+    closed: bool,
+}
+
+impl Backend for ListBackend {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        if self.packets.is_empty() {
+            return Err(RecvError::Closed);
+        }
+        let packet = self.packets.remove(0);
+        if packet.len() > frame.len() {
+            self.dropped += 1;
+            return Err(RecvError::Truncated {
+                len: packet.len(),
+                max: frame.len(),
+            });
+        }
+        frame[..packet.len()].copy_from_slice(&packet);
+        header.caplen = packet.len();
+        header.len = packet.len();
+        Ok(())
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.transmitted.push(frame.to_vec());
+        Ok(())
+    }
+
+    fn stats(&self) -> Stats {
+        Stats {
+            rx_dropped: self.dropped,
+            ..Stats::default()
+        }
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+    }
+}
+
+
+/// Backend which uses the socket owning it, through a handle
+/// set once the socket has been created.
+#[derive(Default)]
+struct ReentrantBackend {
+    socket: Rc<OnceCell<Weak<Socket<ReentrantBackend>>>>,
+}
+
+impl ReentrantBackend {
+    fn socket(&self) -> Rc<Socket<ReentrantBackend>> {
+        self.socket.get().unwrap().upgrade().unwrap()
+    }
+}
+
+impl Backend for ReentrantBackend {
+    fn fill(
+        &mut self,
+        _frame: &mut [u8],
+        _header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        self.socket().try_recv().map(drop)
+    }
+
+    fn transmit(&mut self, frame: &[u8]) -> io::Result<()> {
+        self.socket().send(frame).map_err(io::Error::other)
+    }
+
+    fn stats(&self) -> Stats {
+        self.socket().complete_tx();
+        Stats::default()
+    }
+}
+
+
+fn socket(packets: &[&[u8]]) -> Socket<ListBackend> {
+    let backend = ListBackend {
+        packets: packets.iter().map(|packet| packet.to_vec()).collect(),
+        ..ListBackend::default()
+    };
+    common::socket_over(backend, 2, 4)
+}
+
+
+#[test]
+fn packets_come_from_the_backend() {
+    let socket = socket(&[&[1, 2], &[3, 4, 5, 6, 7], &[8]]);
+
+    let first = socket.recv().unwrap();
+    assert_eq!(first.packet(), &[1, 2]);
+    assert_eq!(first.header().caplen, 2);
+    assert!(matches!(
+        socket.recv(),
+        Err(RecvError::Truncated { len: 5, max: 4 })
+    ));
+    // A failed fill leaves the slot free
+    assert_eq!(socket.recv().unwrap().packet(), &[8]);
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    drop(first);
+    assert!(matches!(socket.recv(), Err(RecvError::Closed)));
+
+    let stats = socket.stats();
+    assert_eq!(stats.rx_packets, 2);
+    assert_eq!(stats.rx_dropped, 1);
+}
+
+#[test]
+fn frames_go_to_the_backend() {
+    let mut socket = socket(&[]);
+
+    socket.send(&[1, 2, 3]).unwrap();
+    let mut slot = socket.reserve_tx().unwrap();
+    slot.packet_mut()[0] = 4;
+    slot.commit(1);
+    assert_eq!(socket.flush(), 2);
+
+    let backend = socket.backend_mut();
+    assert_eq!(backend.transmitted, [vec![1, 2, 3], vec![4]]);
+    assert!(!backend.closed);
+}
+
+#[test]
+fn backend_cannot_reenter_its_socket() {
+    let backend = ReentrantBackend::default();
+    let handle = Rc::clone(&backend.socket);
+    let socket = Rc::new(common::socket_over(backend, 2, 4));
+    handle.set(Rc::downgrade(&socket)).unwrap();
+
+    let reenters = |f: &dyn Fn()| {
+        let err = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        let msg = err.downcast_ref::<&str>().unwrap();
+        assert!(msg.starts_with("socket re-entered"), "{msg}");
+    };
+    reenters(&|| {
+        let _ = socket.try_recv();
+    });
+    socket.send(&[1]).unwrap();
+    reenters(&|| {
+        socket.flush();
+    });
+    reenters(&|| {
+        socket.stats();
+    });
+
+    // The socket is usable again once the backend has failed
+    assert_eq!(socket.complete_tx(), 0);
+}
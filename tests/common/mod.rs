@@ -0,0 +1,35 @@
+//! Helpers shared by the integration tests.
+
+// Each test crate uses only some of the helpers
+#![allow(dead_code)]
+
+use rust_nethuns_miri::{Backend, Socket, SocketOptions, SocketOptionsBuilder};
+
+
+/// Builder of the options of a ring of `num_slots` slots,
+/// whose frames are `frame_size` bytes long.
+pub fn builder(num_slots: usize, frame_size: usize) -> SocketOptionsBuilder {
+    SocketOptions::builder()
+        .num_slots(num_slots)
+        .frame_size(frame_size)
+}
+
+/// Options of a ring of `num_slots` slots,
+/// whose frames are `frame_size` bytes long.
+pub fn opts(num_slots: usize, frame_size: usize) -> SocketOptions {
+    builder(num_slots, frame_size).build().unwrap()
+}
+
+/// Synthetic socket with the ring described by [`opts`].
+pub fn socket(num_slots: usize, frame_size: usize) -> Socket {
+    Socket::new_with(opts(num_slots, frame_size))
+}
+
+/// Socket over `backend`, with the ring described by [`opts`].
+pub fn socket_over<B: Backend>(
+    backend: B,
+    num_slots: usize,
+    frame_size: usize,
+) -> Socket<B> {
+    Socket::with_backend(opts(num_slots, frame_size), backend).unwrap()
+}
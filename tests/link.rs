@@ -0,0 +1,241 @@
+//! Virtual link tests, meant to be run with `cargo +nightly miri test`.
+
+mod common;
+
+use std::thread;
+use std::time::Duration;
+
+use rust_nethuns_miri::{
+    Impairments, LinkBackend, RecvError, Socket, SocketOptions, VirtualLink,
+};
+
+
+fn sockets(link: &VirtualLink) -> (Socket<LinkBackend>, Socket<LinkBackend>) {
+    let opts = common::opts(4, 8);
+    let (a, b) = link.pair();
+    (
+        Socket::with_backend(opts.clone(), a).unwrap(),
+        Socket::with_backend(opts, b).unwrap(),
+    )
+}
+
+
+#[test]
+fn echo() {
+    let (client, server) = sockets(&VirtualLink::new());
+
+    for i in 0..3u8 {
+        client.send(&[i; 3]).unwrap();
+    }
+    assert_eq!(client.flush(), 3);
+    assert!(matches!(client.recv(), Err(RecvError::WouldBlock)));
+
+    // Echo every frame back, straight from the received slot
+    while let Ok(packet) = server.recv() {
+        server.send(packet.packet()).unwrap();
+    }
+    assert_eq!(server.flush(), 3);
+    assert_eq!(server.complete_tx(), 3);
+
+    for i in 0..3u8 {
+        assert_eq!(client.recv().unwrap().packet(), &[i; 3]);
+    }
+}
+
+#[test]
+fn frames_beyond_link_capacity_are_dropped() {
+    let (a, mut b) = sockets(&VirtualLink::new().with_capacity(2));
+
+    for i in 0..3u8 {
+        a.send(&[i]).unwrap();
+    }
+    assert_eq!(a.flush(), 3);
+    assert_eq!(a.stats().tx_dropped, 1);
+    assert_eq!(b.backend_mut().pending(), 2);
+    assert_eq!(b.recv().unwrap().packet(), &[0]);
+    assert_eq!(b.recv().unwrap().packet(), &[1]);
+    assert!(matches!(b.recv(), Err(RecvError::WouldBlock)));
+}
+
+#[test]
+fn oversized_frames_are_dropped() {
+    let (a, b) = VirtualLink::new().pair();
+    let large = SocketOptions::builder().frame_size(8).build().unwrap();
+    let small = SocketOptions::builder().frame_size(4).build().unwrap();
+    let a = Socket::with_backend(large, a).unwrap();
+    let b = Socket::with_backend(small, b).unwrap();
+
+    a.send(&[1; 8]).unwrap();
+    a.send(&[2; 4]).unwrap();
+    a.flush();
+
+    assert!(matches!(
+        b.recv(),
+        Err(RecvError::Truncated { len: 8, max: 4 })
+    ));
+    assert_eq!(b.recv().unwrap().packet(), &[2; 4]);
+    assert_eq!(b.stats().rx_dropped, 1);
+}
+
+#[test]
+fn closed_peer_is_reported_after_pending_frames() {
+    let (a, b) = sockets(&VirtualLink::new());
+
+    a.send(&[7]).unwrap();
+    a.flush();
+    drop(a);
+
+    assert_eq!(b.recv().unwrap().packet(), &[7]);
+    assert!(matches!(b.recv(), Err(RecvError::Closed)));
+}
+
+#[test]
+fn forwarding_across_threads() {
+    const FRAMES: u8 = 6;
+
+    let link = VirtualLink::new();
+    let (source, forwarder_in) = sockets(&link);
+    let (forwarder_out, sink) = sockets(&link);
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            for i in 0..FRAMES {
+                while source.send(&[i]).is_err() {
+                    source.flush();
+                    source.complete_tx();
+                }
+            }
+            source.flush();
+        });
+        s.spawn(move || {
+            let mut forwarded = 0;
+            while forwarded < FRAMES {
+                match forwarder_in.recv() {
+                    Ok(packet) => {
+                        while forwarder_out.send(packet.packet()).is_err() {
+                            forwarder_out.flush();
+                            forwarder_out.complete_tx();
+                        }
+                        forwarded += 1;
+                    }
+                    Err(_) => thread::yield_now(),
+                }
+            }
+            forwarder_out.flush();
+        });
+    });
+
+    for i in 0..FRAMES {
+        assert_eq!(sink.recv().unwrap().packet(), &[i]);
+    }
+    assert!(matches!(sink.recv(), Err(RecvError::Closed)));
+}
+
+fn impaired(impairments: Impairments) -> Vec<Vec<u8>> {
+    let link = VirtualLink::new().with_impairments(impairments);
+    let (a, b) = sockets(&link);
+    for i in 0..32u8 {
+        a.send(&[i, i]).unwrap();
+        a.flush();
+        a.complete_tx();
+    }
+    drop(a);
+
+    let mut received = Vec::new();
+    while let Ok(packet) = b.recv() {
+        received.push(packet.packet().to_vec());
+    }
+    received
+}
+
+#[test]
+fn impairments_are_reproducible() {
+    let impairments = Impairments {
+        seed: 42,
+        loss: 0.2,
+        duplicate: 0.2,
+        reorder: 0.2,
+        corrupt: 0.2,
+        ..Impairments::default()
+    };
+    let first = impaired(impairments.clone());
+    assert_eq!(first, impaired(impairments.clone()));
+    assert_ne!(
+        first,
+        impaired(Impairments {
+            seed: 7,
+            ..impairments
+        })
+    );
+}
+
+#[test]
+fn each_impairment_alters_the_stream() {
+    let clean: Vec<_> = (0..32u8).map(|i| vec![i, i]).collect();
+    assert_eq!(impaired(Impairments::default()), clean);
+
+    let lossy = impaired(Impairments {
+        loss: 0.5,
+        ..Impairments::default()
+    });
+    assert!(lossy.len() < clean.len());
+    assert!(lossy.iter().all(|frame| clean.contains(frame)));
+
+    let duplicated = impaired(Impairments {
+        duplicate: 0.5,
+        ..Impairments::default()
+    });
+    assert!(duplicated.len() > clean.len());
+    assert!(duplicated.windows(2).any(|pair| pair[0] == pair[1]));
+
+    let mut reordered = impaired(Impairments {
+        reorder: 0.5,
+        ..Impairments::default()
+    });
+    assert_ne!(reordered, clean);
+    reordered.sort();
+    assert_eq!(reordered, clean);
+
+    let corrupted = impaired(Impairments {
+        corrupt: 0.5,
+        ..Impairments::default()
+    });
+    assert_eq!(corrupted.len(), clean.len());
+    for (frame, original) in corrupted.iter().zip(&clean) {
+        let flipped: u32 = frame
+            .iter()
+            .zip(original)
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        assert!(flipped <= 1);
+    }
+    assert_ne!(corrupted, clean);
+}
+
+#[test]
+fn delayed_frames_arrive_in_time() {
+    let delay = Duration::from_millis(20);
+    let link = VirtualLink::new().with_impairments(Impairments {
+        delay,
+        rate: Some(1000),
+        ..Impairments::default()
+    });
+    let opts = SocketOptions::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap();
+    let (a, b) = link.pair();
+    let a = Socket::with_backend(opts.clone(), a).unwrap();
+    let b = Socket::with_backend(opts, b).unwrap();
+
+    // 5 bytes at 1000 B/s take 5ms to serialize
+    a.send(&[1; 5]).unwrap();
+    a.send(&[2; 5]).unwrap();
+    a.flush();
+    assert!(matches!(b.try_recv(), Err(RecvError::WouldBlock)));
+
+    let first = b.recv().unwrap().header().timestamp;
+    let second = b.recv().unwrap().header().timestamp;
+    assert!(first >= delay + Duration::from_millis(5));
+    assert!(second >= first + Duration::from_millis(5));
+}
@@ -0,0 +1,83 @@
+//! netmap ring tests, meant to be run with `cargo +nightly miri test`.
+
+mod common;
+
+use std::io;
+
+use rust_nethuns_miri::netmap::NS_BUF_CHANGED;
+use rust_nethuns_miri::{
+    MemoryBackend, NetmapPort, RecvError, Socket, VirtualLink,
+};
+
+
+#[test]
+fn pointers_split_the_slots_between_user_and_kernel() {
+    let mut port = NetmapPort::new_with(common::opts(4, 4));
+    assert!(port.rx().is_empty());
+    assert_eq!(port.tx().space(), 3);
+
+    // One slot is always left to the kernel
+    assert_eq!(port.rxsync().unwrap(), 3);
+    assert_eq!((port.rx().head(), port.rx().tail()), (0, 3));
+    assert_eq!(port.rxsync().unwrap(), 0);
+    assert_eq!(port.rx_packet(2), &[0, 1, 2, 3]);
+
+    port.rx_mut().set_cur(2);
+    assert_eq!(port.rx().space(), 1);
+    port.rx_mut().advance(1);
+    assert_eq!((port.rx().head(), port.rx().cur()), (1, 2));
+    assert_eq!(port.rxsync().unwrap(), 1);
+    assert_eq!(port.rx().tail(), 0);
+}
+
+#[test]
+#[should_panic(expected = "belongs to the kernel")]
+fn kernel_slots_cannot_be_read() {
+    let mut port = NetmapPort::new_with(common::opts(4, 4));
+    port.rxsync().unwrap();
+    port.rx_packet(3);
+}
+
+#[test]
+fn swapped_buffers_are_forwarded_without_copies() {
+    let (a, b) = VirtualLink::new().pair();
+    let mut port = NetmapPort::with_backend(common::opts(4, 4), a).unwrap();
+    let peer = Socket::with_backend(common::opts(4, 4), b).unwrap();
+    peer.send(b"ping").unwrap();
+    peer.flush();
+
+    // Forward the received packet, like a netmap bridge
+    assert_eq!(port.rxsync().unwrap(), 1);
+    let rx = port.rx().cur();
+    let tx = port.tx().cur();
+    assert_eq!(port.rx_packet(rx), b"ping");
+    let (rx_buf, tx_buf) =
+        (port.rx().slot(rx).buf_idx(), port.tx().slot(tx).buf_idx());
+    port.swap(rx, tx);
+    assert_eq!(port.rx().slot(rx).buf_idx(), tx_buf);
+    assert_eq!(port.tx().slot(tx).buf_idx(), rx_buf);
+    assert_eq!(port.tx().slot(tx).flags(), NS_BUF_CHANGED);
+    port.rx_mut().advance(1);
+    port.tx_mut().advance(1);
+    // Then a packet copied into its own buffer
+    port.tx_fill(port.tx().cur(), 2, |buf| buf.copy_from_slice(b"hi"));
+    port.tx_mut().advance(1);
+
+    assert_eq!(port.txsync(), 2);
+    assert_eq!(port.tx().slot(tx).flags(), 0);
+    assert_eq!(port.tx().space(), 3);
+    assert_eq!(port.stats().tx_packets, 2);
+
+    assert_eq!(peer.recv().unwrap().packet(), b"ping");
+    assert_eq!(peer.recv().unwrap().packet(), b"hi");
+    assert!(matches!(peer.recv(), Err(RecvError::WouldBlock)));
+}
+
+#[test]
+fn rings_need_two_slots() {
+    let err =
+        NetmapPort::with_backend(common::opts(1, 4), MemoryBackend::new())
+            .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    assert_eq!(NetmapPort::new_with(common::opts(2, 4)).tx().space(), 1);
+}
@@ -0,0 +1,91 @@
+//! Socket options tests.
+
+use std::io;
+
+use rust_nethuns_miri::options::{MAX_FRAME_SIZE, MAX_SLOTS};
+use rust_nethuns_miri::{
+    Backend, CaptureDir, Direction, OptionsError, PacketHeader, RecvError,
+    Socket, SocketOptions,
+};
+
+
+/// Backend which yields packets going in alternating directions,
+/// whose payload is their sequence number.
+#[derive(Default)]
+struct TwoWayBackend {
+    next: u8,
+}
+
+impl Backend for TwoWayBackend {
+    fn fill(
+        &mut self,
+        frame: &mut [u8],
+        header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        frame[0] = self.next;
+        header.caplen = 1;
+        header.len = 1;
+        header.direction = if self.next.is_multiple_of(2) {
+            Direction::Incoming
+        } else {
+            Direction::Outgoing
+        };
+        self.next += 1;
+        Ok(())
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+
+#[test]
+fn geometry_is_validated() {
+    assert_eq!(
+        SocketOptions::builder().num_slots(0).build(),
+        Err(OptionsError::ZeroSlots)
+    );
+    assert_eq!(
+        SocketOptions::builder().num_slots(MAX_SLOTS + 1).build(),
+        Err(OptionsError::TooManySlots {
+            requested: MAX_SLOTS + 1,
+            max: MAX_SLOTS
+        })
+    );
+    assert_eq!(
+        SocketOptions::builder().frame_size(0).build(),
+        Err(OptionsError::ZeroFrameSize)
+    );
+    assert_eq!(
+        SocketOptions::builder().frame_size(MAX_FRAME_SIZE + 1).build(),
+        Err(OptionsError::FrameTooLarge {
+            requested: MAX_FRAME_SIZE + 1,
+            max: MAX_FRAME_SIZE
+        })
+    );
+
+    let opts = SocketOptions::builder()
+        .num_slots(MAX_SLOTS)
+        .frame_size(MAX_FRAME_SIZE)
+        .build()
+        .unwrap();
+    assert_eq!(opts.num_slots(), MAX_SLOTS);
+    assert_eq!(opts.frame_size(), MAX_FRAME_SIZE);
+}
+
+#[test]
+fn packets_are_filtered_by_direction() {
+    for (dir, expected) in [
+        (CaptureDir::In, [0, 2, 4]),
+        (CaptureDir::Out, [1, 3, 5]),
+        (CaptureDir::InOut, [0, 1, 2]),
+    ] {
+        let opts = SocketOptions::builder().dir(dir).build().unwrap();
+        let socket =
+            Socket::with_backend(opts, TwoWayBackend::default()).unwrap();
+        for seq in expected {
+            assert_eq!(socket.recv().unwrap().packet(), &[seq]);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! PCAP replay tests.
+
+mod common;
+
+use std::cell::RefCell;
+use std::io::{self, Cursor};
+use std::rc::Rc;
+use std::time::Duration;
+
+use rust_nethuns_miri::backend::{PCAP_MAGIC_NSEC, PCAP_MAGIC_USEC};
+use rust_nethuns_miri::writer::{
+    PcapFormat, PcapWriter, RotatingPcapWriter, Rotation, LINKTYPE_ETHERNET,
+};
+use rust_nethuns_miri::{
+    PacketHeader, PcapBackend, RecvError, Socket, SocketOptions,
+};
+
+
+/// Build a pcap file holding `packets`, as (timestamp fraction, payload).
+fn pcap(
+    magic: u32,
+    big_endian: bool,
+    snaplen: u32,
+    packets: &[(u32, &[u8])],
+) -> Vec<u8> {
+    let u32_bytes = |v: u32| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+    let u16_bytes = |v: u16| {
+        if big_endian {
+            v.to_be_bytes()
+        } else {
+            v.to_le_bytes()
+        }
+    };
+
+    let mut file = Vec::new();
+    file.extend(u32_bytes(magic));
+    file.extend(u16_bytes(2));
+    file.extend(u16_bytes(4));
+    file.extend(u32_bytes(0));
+    file.extend(u32_bytes(0));
+    file.extend(u32_bytes(snaplen));
+    file.extend(u32_bytes(1));
+    for (i, (frac, payload)) in packets.iter().enumerate() {
+        file.extend(u32_bytes(100 + i as u32));
+        file.extend(u32_bytes(*frac));
+        file.extend(u32_bytes(payload.len() as u32));
+        file.extend(u32_bytes(payload.len() as u32 + 10));
+        file.extend(*payload);
+    }
+    file
+}
+
+fn socket(file: Vec<u8>) -> Socket<PcapBackend<Cursor<Vec<u8>>>> {
+    common::socket_over(PcapBackend::new(Cursor::new(file)).unwrap(), 2, 4)
+}
+
+
+#[test]
+fn replay_every_format() {
+    for (magic, big_endian, unit) in [
+        (PCAP_MAGIC_USEC, false, 1000),
+        (PCAP_MAGIC_USEC, true, 1000),
+        (PCAP_MAGIC_NSEC, false, 1),
+        (PCAP_MAGIC_NSEC, true, 1),
+    ] {
+        let socket =
+            socket(pcap(magic, big_endian, 65535, &[(7, &[1, 2]), (8, &[3])]));
+
+        let packet = socket.recv().unwrap();
+        assert_eq!(packet.packet(), &[1, 2]);
+        assert_eq!(packet.header().timestamp, Duration::new(100, 7 * unit));
+        assert_eq!(packet.header().caplen, 2);
+        assert_eq!(packet.header().len, 12);
+        drop(packet);
+
+        let packet = socket.recv().unwrap();
+        assert_eq!(packet.packet(), &[3]);
+        assert_eq!(packet.header().timestamp, Duration::new(101, 8 * unit));
+        drop(packet);
+
+        assert!(matches!(socket.recv(), Err(RecvError::Closed)));
+    }
+}
+
+#[test]
+fn packets_are_truncated_to_snaplen_and_frame_size() {
+    let socket = socket(pcap(
+        PCAP_MAGIC_USEC,
+        false,
+        65535,
+        &[(0, &[1, 2, 3, 4, 5, 6]), (0, &[7])],
+    ));
+
+    // Truncated by the frame size, and the rest of the packet is skipped
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.packet(), &[1, 2, 3, 4]);
+    assert_eq!(packet.header().len, 16);
+    assert_eq!(socket.recv().unwrap().packet(), &[7]);
+
+    let file = pcap(PCAP_MAGIC_USEC, false, 65535, &[(0, &[1, 2, 3])]);
+    let backend = PcapBackend::new(Cursor::new(file)).unwrap().with_snaplen(2);
+    let socket =
+        Socket::with_backend(SocketOptions::default(), backend).unwrap();
+    assert_eq!(socket.recv().unwrap().packet(), &[1, 2]);
+}
+
+#[test]
+fn invalid_files_are_rejected() {
+    assert!(PcapBackend::new(Cursor::new(vec![0; 24])).is_err());
+
+    let mut file = pcap(PCAP_MAGIC_USEC, false, 65535, &[(0, &[1, 2, 3])]);
+    file.pop();
+    let socket = socket(file);
+    assert!(matches!(socket.recv(), Err(RecvError::Backend(_))));
+}
+
+#[test]
+fn written_packets_are_replayed() {
+    let socket = Socket::new();
+
+    for format in [PcapFormat::Pcap, PcapFormat::PcapNanos] {
+        let mut writer = PcapWriter::new(Vec::new(), format).unwrap();
+        let first = socket.recv().unwrap();
+        let second = socket.recv().unwrap();
+        // Packets are written while still held
+        writer.write_packet(&first).unwrap();
+        writer.write_packet(&second).unwrap();
+
+        let replay = self::socket(writer.into_inner());
+        let packet = replay.recv().unwrap();
+        assert_eq!(packet.packet(), &first.packet()[..4]);
+        assert_eq!(packet.header().len, 5);
+        let precision = if format == PcapFormat::Pcap { 1000 } else { 1 };
+        assert_eq!(
+            packet.header().timestamp.as_nanos() / precision,
+            first.header().timestamp.as_nanos() / precision
+        );
+    }
+}
+
+#[test]
+fn pcapng_blocks_are_well_formed() {
+    let socket = Socket::new();
+    let mut writer = PcapWriter::new(Vec::new(), PcapFormat::PcapNg).unwrap();
+    writer.write_packet(&socket.recv().unwrap()).unwrap();
+    let file = writer.into_inner();
+
+    // SHB, IDB and one EPB with a 5-byte payload padded to 8 bytes
+    let mut offset = 0;
+    let mut blocks = Vec::new();
+    while offset < file.len() {
+        let kind =
+            u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+        let len = u32::from_le_bytes(
+            file[offset + 4..offset + 8].try_into().unwrap(),
+        ) as usize;
+        let trailer = u32::from_le_bytes(
+            file[offset + len - 4..offset + len].try_into().unwrap(),
+        );
+        assert_eq!(len, trailer as usize);
+        blocks.push((kind, len));
+        offset += len;
+    }
+    assert_eq!(blocks, [(0x0a0d0d0a, 28), (1, 32), (6, 40)]);
+}
+
+#[test]
+fn pcapng_link_types_fit_16_bits() {
+    let err = PcapWriter::with_linktype(Vec::new(), PcapFormat::PcapNg, 65536)
+        .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    let writer =
+        PcapWriter::with_linktype(Vec::new(), PcapFormat::PcapNg, 65535)
+            .unwrap();
+    assert_eq!(writer.into_inner()[36..38], [0xff, 0xff]);
+    // Classic pcap headers have room for 32 bits
+    let writer =
+        PcapWriter::with_linktype(Vec::new(), PcapFormat::Pcap, 65536).unwrap();
+    assert_eq!(writer.linktype(), 65536);
+}
+
+#[test]
+fn files_are_rotated_by_size_and_time() {
+    let files = Rc::new(RefCell::new(Vec::new()));
+    let rotation = Rotation {
+        max_bytes: Some(24 + 2 * (16 + 5)),
+        max_duration: None,
+    };
+    let mut writer = RotatingPcapWriter::new(
+        PcapFormat::Pcap,
+        LINKTYPE_ETHERNET,
+        rotation,
+        {
+            let files = Rc::clone(&files);
+            move |_| {
+                files.borrow_mut().push(());
+                Ok(Vec::new())
+            }
+        },
+    )
+    .unwrap();
+
+    let socket = Socket::new();
+    for _ in 0..5 {
+        writer.write_packet(&socket.recv().unwrap()).unwrap();
+    }
+    assert_eq!(writer.files(), 3);
+    assert_eq!(files.borrow().len(), 3);
+
+    let rotation = Rotation {
+        max_bytes: None,
+        max_duration: Some(Duration::from_secs(10)),
+    };
+    // Raw IP packets
+    let mut writer =
+        RotatingPcapWriter::new(PcapFormat::PcapNg, 101, rotation, |_| {
+            Ok(Vec::new())
+        })
+        .unwrap();
+    for secs in [0, 5, 9, 10, 25] {
+        let header = PacketHeader {
+            timestamp: Duration::from_secs(secs),
+            ..PacketHeader::default()
+        };
+        writer.write(&header, &[0; 4]).unwrap();
+    }
+    assert_eq!(writer.files(), 3);
+    assert_eq!(writer.current().packets_written(), 1);
+    assert_eq!(writer.current().linktype(), 101);
+}
+
+#[test]
+fn payloads_are_truncated_to_the_snaplen() {
+    let mut writer = PcapWriter::new(Vec::new(), PcapFormat::Pcap).unwrap();
+    let snaplen = writer.snaplen() as usize;
+    let payload = vec![7; snaplen + 10];
+    let header = PacketHeader {
+        len: payload.len(),
+        ..PacketHeader::default()
+    };
+    writer.write(&header, &payload).unwrap();
+    assert_eq!(writer.bytes_written(), (24 + 16 + snaplen) as u64);
+
+    let file = writer.into_inner();
+    let field = |offset: usize| {
+        u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap())
+    };
+    // Stored and original length of the record
+    assert_eq!(field(24 + 8) as usize, snaplen);
+    assert_eq!(field(24 + 12) as usize, snaplen + 10);
+}
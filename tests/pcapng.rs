@@ -0,0 +1,313 @@
+//! PCAPNG replay tests.
+
+mod common;
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use rust_nethuns_miri::{
+    Direction, PcapFormat, PcapNgBackend, PcapWriter, RecvError, Socket,
+    SocketOptions,
+};
+
+
+/// Builder of pcapng files, whose sections are in the byte order
+/// of the last section header.
+#[derive(Default)]
+struct PcapNg {
+    data: Vec<u8>,
+    big_endian: bool,
+}
+
+impl PcapNg {
+    /// Convert little-endian `bytes` to the byte order of the section.
+    fn ord<const N: usize>(&self, mut bytes: [u8; N]) -> [u8; N] {
+        if self.big_endian {
+            bytes.reverse();
+        }
+        bytes
+    }
+
+    fn option(&self, body: &mut Vec<u8>, code: u16, value: &[u8]) {
+        body.extend(self.ord(code.to_le_bytes()));
+        body.extend(self.ord((value.len() as u16).to_le_bytes()));
+        body.extend(value);
+        body.resize(body.len().next_multiple_of(4), 0);
+    }
+
+    fn block(&mut self, kind: u32, body: &[u8]) -> &mut Self {
+        let len = self.ord((12 + body.len() as u32).to_le_bytes());
+        self.data.extend(self.ord(kind.to_le_bytes()));
+        self.data.extend(len);
+        self.data.extend(body);
+        self.data.extend(len);
+        self
+    }
+
+    fn section(&mut self) -> &mut Self {
+        self.section_with_order(false)
+    }
+
+    fn section_with_order(&mut self, big_endian: bool) -> &mut Self {
+        self.big_endian = big_endian;
+        let mut body = Vec::new();
+        body.extend(self.ord(0x1a2b3c4du32.to_le_bytes()));
+        body.extend(self.ord(1u16.to_le_bytes()));
+        body.extend(self.ord(0u16.to_le_bytes()));
+        body.extend(self.ord((-1i64).to_le_bytes()));
+        self.block(0x0a0d0d0a, &body)
+    }
+
+    fn interface(&mut self, name: &str, tsresol: Option<u8>) -> &mut Self {
+        self.interface_with_offset(name, tsresol, None)
+    }
+
+    fn interface_with_offset(
+        &mut self,
+        name: &str,
+        tsresol: Option<u8>,
+        tsoffset: Option<i64>,
+    ) -> &mut Self {
+        let mut body = Vec::new();
+        body.extend(self.ord(1u16.to_le_bytes()));
+        body.extend(self.ord(0u16.to_le_bytes()));
+        body.extend(self.ord(0u32.to_le_bytes()));
+        self.option(&mut body, 2, name.as_bytes());
+        if let Some(tsresol) = tsresol {
+            self.option(&mut body, 9, &[tsresol]);
+        }
+        if let Some(tsoffset) = tsoffset {
+            self.option(&mut body, 14, &self.ord(tsoffset.to_le_bytes()));
+        }
+        self.option(&mut body, 0, &[]);
+        self.block(1, &body)
+    }
+
+    fn packet(
+        &mut self,
+        id: u32,
+        units: u64,
+        data: &[u8],
+        comment: Option<&str>,
+    ) -> &mut Self {
+        self.packet_with_flags(id, units, data, comment, None)
+    }
+
+    fn packet_with_flags(
+        &mut self,
+        id: u32,
+        units: u64,
+        data: &[u8],
+        comment: Option<&str>,
+        flags: Option<u32>,
+    ) -> &mut Self {
+        let mut body = Vec::new();
+        body.extend(self.ord(id.to_le_bytes()));
+        body.extend(self.ord(((units >> 32) as u32).to_le_bytes()));
+        body.extend(self.ord((units as u32).to_le_bytes()));
+        body.extend(self.ord((data.len() as u32).to_le_bytes()));
+        body.extend(self.ord((data.len() as u32).to_le_bytes()));
+        body.extend(data);
+        body.resize(body.len().next_multiple_of(4), 0);
+        if let Some(comment) = comment {
+            self.option(&mut body, 1, comment.as_bytes());
+        }
+        if let Some(flags) = flags {
+            self.option(&mut body, 2, &self.ord(flags.to_le_bytes()));
+        }
+        if comment.is_some() || flags.is_some() {
+            self.option(&mut body, 0, &[]);
+        }
+        self.block(6, &body)
+    }
+
+    fn simple_packet(&mut self, len: u32, data: &[u8]) -> &mut Self {
+        let mut body = Vec::new();
+        body.extend(self.ord(len.to_le_bytes()));
+        body.extend(data);
+        body.resize(body.len().next_multiple_of(4), 0);
+        self.block(3, &body)
+    }
+
+    fn socket(&self) -> Socket<PcapNgBackend<Cursor<Vec<u8>>>> {
+        let backend =
+            PcapNgBackend::new(Cursor::new(self.data.clone())).unwrap();
+        common::socket_over(backend, 2, 4)
+    }
+}
+
+
+#[test]
+fn packets_carry_their_interface_and_comment() {
+    let socket = PcapNg::default()
+        .section()
+        .interface("eth0", None)
+        .interface("eth1", Some(9))
+        .block(0x0bad, &[0; 8])
+        .packet(1, 3_000_000_007, &[1, 2, 3], Some("first"))
+        .packet(0, 2_000_005, &[4, 5, 6, 7, 8], None)
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    let header = packet.header();
+    assert_eq!(packet.packet(), &[1, 2, 3]);
+    assert_eq!(header.ifindex, 1);
+    assert_eq!(header.timestamp, Duration::new(3, 7));
+    assert_eq!(header.comment.as_deref(), Some("first"));
+    drop(packet);
+
+    // Default resolution is microseconds, and data is truncated
+    // to the frame size
+    let packet = socket.recv().unwrap();
+    let header = packet.header();
+    assert_eq!(packet.packet(), &[4, 5, 6, 7]);
+    assert_eq!((header.caplen, header.len), (4, 5));
+    assert_eq!(header.ifindex, 0);
+    assert_eq!(header.timestamp, Duration::new(2, 5_000));
+    assert_eq!(header.comment, None);
+    drop(packet);
+
+    assert!(matches!(socket.recv(), Err(RecvError::Closed)));
+}
+
+#[test]
+fn new_section_resets_interfaces() {
+    let socket = PcapNg::default()
+        .section()
+        .interface("eth0", None)
+        .interface("eth1", None)
+        .section()
+        .interface("eth2", Some(0x80 | 10))
+        .packet(0, 3 << 10 | 512, &[1], None)
+        .packet(1, 0, &[2], None)
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.header().timestamp, Duration::from_millis(3500));
+    assert!(matches!(socket.recv(), Err(RecvError::Backend(_))));
+}
+
+#[test]
+fn direction_follows_the_packet_flags() {
+    let socket = PcapNg::default()
+        .section()
+        .interface("eth0", None)
+        .packet_with_flags(0, 0, &[1], Some("out"), Some(0x2))
+        .packet_with_flags(0, 0, &[2], None, Some(0x1))
+        .packet(0, 0, &[3], None)
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.header().direction, Direction::Outgoing);
+    assert_eq!(packet.header().comment.as_deref(), Some("out"));
+    drop(packet);
+    for _ in 0..2 {
+        let packet = socket.recv().unwrap();
+        assert_eq!(packet.header().direction, Direction::Incoming);
+    }
+}
+
+#[test]
+fn simple_packets_belong_to_the_first_interface() {
+    let socket = PcapNg::default()
+        .section()
+        .interface("eth0", None)
+        .interface("eth1", None)
+        .simple_packet(3, &[1, 2, 3])
+        .simple_packet(6, &[4, 5, 6, 7, 8, 9])
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    let header = packet.header();
+    assert_eq!(packet.packet(), &[1, 2, 3]);
+    assert_eq!((header.caplen, header.len), (3, 3));
+    assert_eq!(header.ifindex, 0);
+    assert_eq!(header.timestamp, Duration::ZERO);
+    drop(packet);
+
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.packet(), &[4, 5, 6, 7]);
+    assert_eq!((packet.header().caplen, packet.header().len), (4, 6));
+    drop(packet);
+
+    assert!(matches!(socket.recv(), Err(RecvError::Closed)));
+}
+
+#[test]
+fn big_endian_sections_are_replayed() {
+    let socket = PcapNg::default()
+        .section_with_order(true)
+        .interface_with_offset("eth0", Some(9), Some(10))
+        .packet_with_flags(0, 3_000_000_007, &[1, 2, 3], Some("be"), Some(0x2))
+        .simple_packet(2, &[4, 5])
+        .section()
+        .interface("eth1", None)
+        .packet(0, 1_000_000, &[6], None)
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    let header = packet.header();
+    assert_eq!(packet.packet(), &[1, 2, 3]);
+    assert_eq!(header.timestamp, Duration::new(13, 7));
+    assert_eq!(header.comment.as_deref(), Some("be"));
+    assert_eq!(header.direction, Direction::Outgoing);
+    drop(packet);
+    assert_eq!(socket.recv().unwrap().packet(), &[4, 5]);
+
+    // The next section is little-endian again
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.packet(), &[6]);
+    assert_eq!(packet.header().timestamp, Duration::from_secs(1));
+}
+
+#[test]
+fn unsupported_resolution_is_an_error() {
+    let socket = PcapNg::default()
+        .section()
+        .interface("eth0", Some(40))
+        .packet(0, 1, &[1], None)
+        .socket();
+
+    assert!(matches!(socket.recv(), Err(RecvError::Backend(_))));
+}
+
+#[test]
+fn timestamps_out_of_range_are_errors() {
+    let socket = PcapNg::default()
+        .section()
+        .interface_with_offset("eth0", Some(0), Some(-5))
+        .interface_with_offset("eth1", Some(0), Some(i64::MAX))
+        .packet(0, 7, &[1], None)
+        .packet(0, 3, &[2], None)
+        .packet(1, u64::MAX, &[3], None)
+        .packet(1, 0, &[4], None)
+        .socket();
+
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.header().timestamp, Duration::from_secs(2));
+    drop(packet);
+    assert!(matches!(socket.recv(), Err(RecvError::Backend(_))));
+    assert!(matches!(socket.recv(), Err(RecvError::Backend(_))));
+    // Packets out of range are skipped whole
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.packet(), &[4]);
+    assert_eq!(
+        packet.header().timestamp,
+        Duration::from_secs(i64::MAX as u64)
+    );
+}
+
+#[test]
+fn written_pcapng_is_replayed() {
+    let mut writer = PcapWriter::new(Vec::new(), PcapFormat::PcapNg).unwrap();
+    let source = Socket::new();
+    let sent = source.recv().unwrap();
+    writer.write_packet(&sent).unwrap();
+
+    let backend = PcapNgBackend::new(Cursor::new(writer.into_inner())).unwrap();
+    let socket =
+        Socket::with_backend(SocketOptions::default(), backend).unwrap();
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.packet(), sent.packet());
+    assert_eq!(packet.header().timestamp, sent.header().timestamp);
+}
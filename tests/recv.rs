@@ -0,0 +1,97 @@
+//! RX path tests, meant to be run with `cargo +nightly miri test`.
+
+use rust_nethuns_miri::options::MAX_FRAME_ALIGN;
+use rust_nethuns_miri::{
+    Direction, OptionsError, PacketBatch, RecvError, Socket, SocketOptions,
+};
+
+
+#[test]
+fn received_packet_carries_metadata() {
+    let socket = Socket::new_with(
+        SocketOptions::builder()
+            .frame_size(8)
+            .rxhash(true)
+            .build()
+            .unwrap(),
+    );
+
+    let first = socket.recv().unwrap();
+    let second = socket.recv().unwrap();
+    let header = first.header();
+    assert_eq!(header.caplen, 8);
+    assert_eq!(header.len, 8);
+    assert_eq!(header.direction, Direction::Incoming);
+    assert!(header.rxhash.is_some());
+    assert_eq!(header.rxhash, second.header().rxhash);
+    assert!(header.timestamp <= second.header().timestamp);
+}
+
+#[test]
+fn burst_stops_at_first_held_slot() {
+    let socket = Socket::new();
+    let mut batch = PacketBatch::new();
+
+    let held = socket.recv().unwrap();
+    assert_eq!(socket.recv_burst(&mut batch, 8).unwrap(), 4);
+    let idx: Vec<usize> = batch.iter().map(|packet| packet.idx()).collect();
+    assert_eq!(idx, [1, 2, 3, 4]);
+    assert!(matches!(
+        socket.recv_burst(&mut batch, 8),
+        Err(RecvError::WouldBlock)
+    ));
+
+    // Slot 0 is the next one, so releasing it alone is enough
+    drop(held);
+    assert_eq!(socket.recv_burst(&mut batch, 8).unwrap(), 1);
+    assert_eq!(batch.len(), 5);
+    assert_eq!(socket.stats().rx_packets, 6);
+}
+
+#[test]
+fn burst_packets_are_released_one_by_one_or_together() {
+    let socket = Socket::new();
+    let mut batch = PacketBatch::with_capacity(5);
+
+    assert_eq!(socket.recv_burst(&mut batch, 2).unwrap(), 2);
+    assert_eq!(socket.recv_burst(&mut batch, 0).unwrap(), 0);
+    let first = batch.remove(0);
+    assert_eq!(first.idx(), 0);
+    drop(first);
+    batch.clear();
+
+    assert_eq!(socket.recv_burst(&mut batch, 5).unwrap(), 5);
+    let idx: Vec<usize> = batch.drain(..2).map(|packet| packet.idx()).collect();
+    assert_eq!(idx, [2, 3]);
+    // Slots 2 and 3 have been released by `drain`
+    drop(batch);
+    assert_eq!(socket.recv_burst(&mut PacketBatch::new(), 5).unwrap(), 5);
+}
+
+#[test]
+fn packets_borrow_aligned_frames_of_one_arena() {
+    let socket = Socket::new_with(
+        SocketOptions::builder()
+            .num_slots(3)
+            .frame_size(5)
+            .frame_align(64)
+            .build()
+            .unwrap(),
+    );
+    assert_eq!(
+        SocketOptions::builder().frame_align(3).build(),
+        Err(OptionsError::BadFrameAlign {
+            requested: 3,
+            max: MAX_FRAME_ALIGN
+        })
+    );
+
+    // All the packets are alive at once, as disjoint borrows
+    let packets: Vec<_> = (0..3).map(|_| socket.recv().unwrap()).collect();
+    let base = packets[0].packet().as_ptr();
+    for (k, packet) in packets.iter().enumerate() {
+        assert_eq!(packet.packet(), &[0, 1, 2, 3, 4]);
+        assert_eq!(packet.packet().as_ptr() as usize % 64, 0);
+        assert_eq!(packet.packet().as_ptr(), base.wrapping_add(k * 64));
+    }
+}
@@ -0,0 +1,120 @@
+//! Release-by-id tests, meant to be run with `cargo +nightly miri test`.
+
+mod common;
+
+use std::time::Duration;
+use std::{mem, thread};
+
+use rust_nethuns_miri::{
+    PacketId, RecvError, ReleaseError, SharedSocket, SlotState,
+};
+
+
+#[test]
+fn detached_packets_are_released_out_of_order() {
+    let socket = common::socket(3, 5);
+    let ids: Vec<_> =
+        (0..3).map(|_| socket.recv().unwrap().into_id()).collect();
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+
+    // Releasing a later packet does not unblock the ring
+    socket.release(ids[1]).unwrap();
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    socket.release(ids[0]).unwrap();
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.idx(), 0);
+    assert_eq!(
+        packet.to_string(),
+        "idx: 0, status: USER, packet: [0, 1, 2, 3, 4]"
+    );
+    drop(packet);
+
+    socket.release(ids[2]).unwrap();
+    assert_eq!(socket.recv().unwrap().idx(), 1);
+}
+
+#[test]
+fn invalid_releases_are_rejected() {
+    let socket = common::socket(3, 5);
+
+    let packet = socket.recv().unwrap();
+    let id = packet.id();
+    assert_eq!(socket.release(id), Err(ReleaseError::HandleAlive(id)));
+
+    let id = packet.into_id();
+    socket.release(id).unwrap();
+    assert_eq!(socket.release(id), Err(ReleaseError::DoubleRelease(id)));
+
+    let larger = common::socket(5, 5);
+    let foreign = (0..4).map(|_| larger.recv().unwrap().into_id()).last();
+    let foreign = foreign.unwrap();
+    assert_eq!(foreign.idx(), 3);
+    assert_eq!(
+        socket.release(foreign),
+        Err(ReleaseError::UnknownId(foreign))
+    );
+}
+
+#[test]
+fn ids_can_be_released_from_another_thread() {
+    let socket = SharedSocket::new();
+    let id = socket.recv().unwrap().into_id();
+
+    thread::scope(|s| {
+        s.spawn(|| socket.release(id).unwrap());
+    });
+    assert_eq!(socket.release(id), Err(ReleaseError::DoubleRelease(id)));
+}
+
+#[test]
+fn stale_ids_do_not_touch_reused_slots() {
+    let socket = common::socket(1, 5);
+    let old = socket.recv().unwrap().into_id();
+    socket.release(old).unwrap();
+
+    // The slot wraps around and holds a new packet
+    let new = socket.recv().unwrap().into_id();
+    assert_eq!(new.idx(), old.idx());
+    assert_ne!(new, old);
+    assert_eq!(socket.lookup(old), Err(ReleaseError::Stale(old)));
+    assert_eq!(socket.release(old), Err(ReleaseError::Stale(old)));
+    assert_eq!(socket.lookup(new), Ok(SlotState::PendingRelease));
+
+    // Ids survive a round trip through their C representation
+    let raw = new.into_raw();
+    assert_eq!(raw >> 32, new.generation() as u64);
+    socket.release(PacketId::from_raw(raw)).unwrap();
+    assert_eq!(socket.lookup(new), Ok(SlotState::Free));
+}
+
+#[test]
+fn leaked_packets_are_reported_and_reclaimed() {
+    let socket = common::socket(2, 5);
+    mem::forget(socket.recv().unwrap());
+    let detached = socket.recv().unwrap().into_id();
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    assert!(socket
+        .held_longer_than(Duration::from_secs(3600))
+        .is_empty());
+
+    let held = socket.held_longer_than(Duration::ZERO);
+    assert_eq!(held.len(), 2);
+    assert_eq!(held[1], detached);
+    let leaked = held[0];
+    assert_eq!(socket.lookup(leaked), Ok(SlotState::User));
+
+    // SAFETY: the handle of the packet has been leaked.
+    unsafe { socket.force_reclaim(leaked) }.unwrap();
+    assert_eq!(
+        unsafe { socket.force_reclaim(leaked) },
+        Err(ReleaseError::DoubleRelease(leaked))
+    );
+    let packet = socket.recv().unwrap();
+    assert_eq!(packet.idx(), leaked.idx());
+    assert_eq!(
+        unsafe { socket.force_reclaim(leaked) },
+        Err(ReleaseError::Stale(leaked))
+    );
+    drop(packet);
+    socket.release(detached).unwrap();
+}
@@ -0,0 +1,88 @@
+//! Tests of packets released on another thread, meant to be run with
+//! `cargo +nightly miri test`.
+
+mod common;
+
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use rust_nethuns_miri::{RecvError, RecvPacket, Socket};
+
+
+#[test]
+fn packet_dropped_on_worker_is_seen_as_free() {
+    let socket = common::socket(2, 4);
+
+    thread::scope(|s| {
+        let packet = socket.recv().unwrap();
+        assert_eq!(packet.idx(), 0);
+        s.spawn(move || {
+            assert_eq!(packet.packet(), &[0, 1, 2, 3]);
+            drop(packet);
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(socket.recv().unwrap().idx(), 1);
+        // Slot 0 has been released by the worker
+        assert_eq!(socket.recv().unwrap().idx(), 0);
+    });
+}
+
+#[test]
+fn io_thread_receives_while_workers_drop() {
+    const WORKERS: usize = 2;
+    const PACKETS: usize = 8;
+
+    let socket = common::socket(3, 4);
+
+    thread::scope(|s| {
+        let mut senders = Vec::new();
+        for _ in 0..WORKERS {
+            let (tx, rx) = mpsc::channel::<RecvPacket>();
+            senders.push(tx);
+            s.spawn(move || {
+                for packet in rx {
+                    assert_eq!(packet.packet(), &[0, 1, 2, 3]);
+                }
+            });
+        }
+
+        // The ring is smaller than the number of packets, so the I/O
+        // thread can only proceed once workers have released slots
+        let mut received = 0;
+        while received < PACKETS {
+            match socket.recv() {
+                Ok(packet) => {
+                    senders[received % WORKERS].send(packet).unwrap();
+                    received += 1;
+                }
+                Err(_) => thread::yield_now(),
+            }
+        }
+    });
+
+    assert_eq!(socket.stats().rx_packets, PACKETS as u64);
+    // Every packet has been dropped by a worker
+    let packets: Vec<_> = (0..3).map_while(|_| socket.recv().ok()).collect();
+    assert_eq!(packets.len(), 3);
+}
+
+#[test]
+fn recv_waits_for_worker_to_release() {
+    let socket = Socket::new_with(
+        common::builder(1, 4)
+            .timeout(Duration::from_secs(60))
+            .build()
+            .unwrap(),
+    );
+
+    thread::scope(|s| {
+        let packet = socket.recv().unwrap();
+        assert!(matches!(socket.try_recv(), Err(RecvError::WouldBlock)));
+        s.spawn(move || drop(packet));
+        // Blocks until the worker has dropped the packet
+        assert_eq!(socket.recv().unwrap().idx(), 0);
+    });
+}
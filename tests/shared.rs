@@ -0,0 +1,90 @@
+//! Multi-threaded receive tests, meant to be run with
+//! `cargo +nightly miri test`.
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use rust_nethuns_miri::SharedSocket;
+
+
+const THREADS: usize = 3;
+const PACKETS_PER_THREAD: usize = 4;
+
+
+#[test]
+fn concurrent_recv_and_drop() {
+    let socket = SharedSocket::new_with(common::opts(2, 4));
+    let received = AtomicUsize::new(0);
+
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                let mut count = 0;
+                while count < PACKETS_PER_THREAD {
+                    // The ring is smaller than the number of threads,
+                    // so receives fail until a worker drops its packet
+                    let Ok(packet) = socket.recv() else {
+                        thread::yield_now();
+                        continue;
+                    };
+                    assert_eq!(packet.packet(), &[0, 1, 2, 3]);
+                    count += 1;
+                }
+                received.fetch_add(count, Ordering::Relaxed);
+            });
+        }
+    });
+
+    let total = THREADS * PACKETS_PER_THREAD;
+    assert_eq!(received.load(Ordering::Relaxed), total);
+    assert_eq!(socket.stats().rx_packets, total as u64);
+}
+
+#[test]
+fn packets_held_across_receives() {
+    let socket = SharedSocket::new_with(common::opts(THREADS, 4));
+
+    thread::scope(|s| {
+        for _ in 0..THREADS {
+            s.spawn(|| {
+                let first = loop {
+                    if let Ok(packet) = socket.recv() {
+                        break packet;
+                    }
+                    thread::yield_now();
+                };
+                // Other threads keep mutating the ring
+                // while this packet is alive
+                thread::yield_now();
+                assert_eq!(first.packet(), &[0, 1, 2, 3]);
+            });
+        }
+    });
+
+    // Every slot has been released
+    for _ in 0..THREADS {
+        assert!(socket.recv().is_ok());
+    }
+}
+
+#[test]
+fn concurrent_send_and_flush() {
+    let socket = SharedSocket::new_with(common::opts(4, 4));
+
+    thread::scope(|s| {
+        for i in 0..THREADS as u8 {
+            let socket = &socket;
+            s.spawn(move || {
+                while socket.send(&[i; 4]).is_err() {
+                    socket.flush();
+                    socket.complete_tx();
+                }
+            });
+        }
+    });
+
+    socket.flush();
+    assert_eq!(socket.stats().tx_packets, THREADS as u64);
+}
@@ -0,0 +1,174 @@
+//! Shared-memory ring tests.
+//!
+//! Miri cannot emulate `mmap`, so the tests of file-backed rings are
+//! ignored under Miri, which checks a heap-backed ring instead.
+#![cfg(unix)]
+
+mod common;
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+use std::{env, fs, process, thread};
+
+use rust_nethuns_miri::{
+    RecvError, RecvPacket, ReleaseError, SendError, ShmProducer, ShmRing,
+    ShmSocket,
+};
+
+
+/// Environment variable telling the child process which ring to fill.
+const PRODUCER_RING: &str = "RUST_NETHUNS_SHM_PRODUCER";
+const FRAMES: u32 = 500;
+/// Frames exchanged between threads, few enough for Miri.
+const THREAD_FRAMES: u32 = 40;
+
+
+fn ring_path(name: &str) -> PathBuf {
+    let dir = PathBuf::from("/dev/shm");
+    let dir = if dir.is_dir() { dir } else { env::temp_dir() };
+    dir.join(format!("rust-nethuns-{name}-{}", process::id()))
+}
+
+/// Frame of sequence number `seq`.
+fn frame(seq: u32) -> Vec<u8> {
+    [seq.to_le_bytes(), (!seq).to_le_bytes()].concat()
+}
+
+fn recv_one(socket: &ShmSocket) -> RecvPacket<'_> {
+    loop {
+        match socket.recv() {
+            Ok(packet) => return packet,
+            Err(RecvError::WouldBlock) => thread::yield_now(),
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_flow_through_the_mapping() {
+    let path = ring_path("flow");
+    let mut producer =
+        ShmProducer::new(ShmRing::create(&path, &common::opts(2, 8)).unwrap());
+    let socket = ShmSocket::new(ShmRing::open(&path).unwrap());
+    assert_eq!(socket.ring().num_slots(), 2);
+
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    producer.send(&[1; 3]).unwrap();
+    producer.send(&[2; 8]).unwrap();
+    assert_eq!(producer.send(&[3]), Err(SendError::RingFull));
+    assert_eq!(
+        producer.send(&[0; 9]),
+        Err(SendError::FrameTooLarge { len: 9, max: 8 })
+    );
+
+    // Packets borrow the frames of the mapping
+    let first = socket.recv().unwrap();
+    assert_eq!(first.packet(), &[1; 3]);
+    assert_eq!(first.header().len, 3);
+    let second = socket.recv().unwrap().into_id();
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    assert_eq!(socket.stats().rx_ring_full, 1);
+
+    // Each shared slot is freed when its packet is released
+    assert_eq!(producer.send(&[3]), Err(SendError::RingFull));
+    drop(first);
+    producer.send(&[3]).unwrap();
+    assert_eq!(producer.send(&[4]), Err(SendError::RingFull));
+    assert_eq!(socket.release(second), Ok(()));
+    assert_eq!(
+        socket.release(second),
+        Err(ReleaseError::DoubleRelease(second))
+    );
+    producer.send(&[4]).unwrap();
+
+    assert_eq!(socket.recv().unwrap().packet(), &[3]);
+    assert_eq!(socket.recv().unwrap().packet(), &[4]);
+    assert_eq!(socket.stats().rx_packets, 4);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn foreign_files_are_rejected() {
+    let path = ring_path("foreign");
+    fs::write(&path, [0xff; 128]).unwrap();
+    let err = ShmRing::open(&path).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
+
+/// Body of the producer process spawned by `frames_cross_processes`,
+/// a no-op when run as a regular test.
+#[test]
+#[cfg_attr(miri, ignore)]
+fn producer_process() {
+    let Some(path) = env::var_os(PRODUCER_RING) else {
+        return;
+    };
+    let mut producer = ShmProducer::new(ShmRing::open(path).unwrap());
+    for seq in 0..FRAMES {
+        while producer.send(&frame(seq)).is_err() {
+            thread::yield_now();
+        }
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_cross_processes() {
+    let path = ring_path("processes");
+    let ring = ShmRing::create(&path, &common::opts(4, 8)).unwrap();
+    let socket = ShmSocket::new(ring);
+
+    let mut child = Command::new(env::current_exe().unwrap())
+        .args(["--exact", "producer_process", "--include-ignored"])
+        .env(PRODUCER_RING, &path)
+        .spawn()
+        .unwrap();
+
+    // Every frame must be complete once its status byte says so
+    for seq in 0..FRAMES {
+        assert_eq!(recv_one(&socket).packet(), frame(seq));
+    }
+    assert!(child.wait().unwrap().success());
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn frames_cross_threads_through_a_heap_ring() {
+    let (ring, view) = ShmRing::pair(&common::opts(4, 8));
+    let mut producer = ShmProducer::new(ring);
+    let socket = ShmSocket::new(view);
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            for seq in 0..THREAD_FRAMES {
+                let timestamp = Duration::from_nanos(seq.into());
+                while producer.send_at(&frame(seq), timestamp).is_err() {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        // Hold a few packets at a time, so that the producer
+        // waits for their release
+        let mut held = VecDeque::new();
+        for seq in 0..THREAD_FRAMES {
+            let packet = recv_one(&socket);
+            assert_eq!(packet.packet(), frame(seq));
+            assert_eq!(
+                packet.header().timestamp,
+                Duration::from_nanos(seq.into())
+            );
+            held.push_back(packet);
+            if held.len() == 3 {
+                held.pop_front();
+            }
+        }
+    });
+    assert_eq!(socket.stats().rx_packets, THREAD_FRAMES.into());
+}
@@ -0,0 +1,48 @@
+//! Slot lifecycle tests.
+
+use rust_nethuns_miri::{SlotState, SlotStatus, Socket};
+
+
+#[test]
+fn double_release_is_rejected() {
+    let status = SlotStatus::new(SlotState::User);
+
+    status.transition(SlotState::User, SlotState::Free).unwrap();
+    let err = status
+        .transition(SlotState::User, SlotState::Free)
+        .unwrap_err();
+    assert_eq!(err.actual, SlotState::Free);
+    assert_eq!(status.load(), SlotState::Free);
+}
+
+#[test]
+fn release_of_slot_never_handed_out_is_rejected() {
+    let status = SlotStatus::new(SlotState::Backend);
+
+    assert!(status.transition(SlotState::User, SlotState::Free).is_err());
+    assert_eq!(status.load(), SlotState::Backend);
+}
+
+#[test]
+fn illegal_transition_is_rejected() {
+    let status = SlotStatus::new(SlotState::Free);
+
+    assert!(!SlotState::Free.can_transition_to(SlotState::InFlight));
+    assert!(status
+        .transition(SlotState::Free, SlotState::InFlight)
+        .is_err());
+    assert_eq!(status.load(), SlotState::Free);
+}
+
+#[test]
+fn received_slot_is_held_by_user() {
+    let socket = Socket::new();
+
+    let packet = socket.recv().unwrap();
+    assert_eq!(
+        packet.to_string(),
+        "idx: 0, status: USER, packet: [0, 1, 2, 3, 4]"
+    );
+    drop(packet);
+    assert!(socket.recv().is_ok());
+}
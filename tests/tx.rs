@@ -0,0 +1,118 @@
+//! TX path tests, meant to be run with `cargo +nightly miri test`.
+
+mod common;
+
+use rust_nethuns_miri::{SendError, SocketOptions, TxRing};
+
+
+#[test]
+fn send_fills_the_ring_until_completion() {
+    let socket = common::socket(3, 8);
+
+    for i in 0..3u8 {
+        socket.send(&[i; 4]).unwrap();
+    }
+    assert_eq!(socket.send(&[3; 4]), Err(SendError::RingFull));
+
+    // Flushing alone does not free the slots
+    assert_eq!(socket.flush(), 3);
+    assert_eq!(socket.send(&[3; 4]), Err(SendError::RingFull));
+
+    assert_eq!(socket.complete_tx(), 3);
+    socket.send(&[3; 4]).unwrap();
+
+    let stats = socket.stats();
+    assert_eq!(stats.tx_packets, 3);
+    assert_eq!(stats.tx_ring_full, 2);
+}
+
+#[test]
+fn completion_only_reclaims_flushed_slots() {
+    let socket = common::socket(4, 8);
+
+    socket.send(&[0; 8]).unwrap();
+    socket.send(&[1; 8]).unwrap();
+    assert_eq!(socket.complete_tx(), 0);
+
+    assert_eq!(socket.flush(), 2);
+    socket.send(&[2; 8]).unwrap();
+    assert_eq!(socket.complete_tx(), 2);
+    assert_eq!(socket.flush(), 1);
+    assert_eq!(socket.complete_tx(), 1);
+}
+
+#[test]
+fn queued_frames_are_visible() {
+    let mut ring = TxRing::with_options(&SocketOptions::default());
+    ring.send(&[1, 2, 3]).unwrap();
+
+    assert_eq!(ring.frame(0), Some(&[1, 2, 3][..]));
+    assert_eq!(ring.frame(1), None);
+    assert_eq!(ring.frame(usize::MAX), None);
+}
+
+#[test]
+fn oversized_frame_is_rejected() {
+    let socket = common::socket(2, 4);
+
+    assert_eq!(
+        socket.send(&[0; 5]),
+        Err(SendError::FrameTooLarge { len: 5, max: 4 })
+    );
+    assert_eq!(socket.flush(), 0);
+}
+
+#[test]
+fn send_while_packets_are_received() {
+    let socket = common::socket(2, 4);
+
+    let packet = socket.recv().unwrap();
+    socket.send(packet.packet()).unwrap();
+    assert_eq!(socket.flush(), 1);
+    assert_eq!(packet.packet(), &[0, 1, 2, 3]);
+    drop(packet);
+    assert_eq!(socket.complete_tx(), 1);
+}
+
+#[test]
+fn reserved_slot_is_committed_in_place() {
+    let socket = common::socket(2, 8);
+
+    let mut slot = socket.reserve_tx().unwrap();
+    assert_eq!(slot.packet().len(), 8);
+    // Only one slot can be reserved at a time
+    assert!(socket.reserve_tx().is_err());
+    assert_eq!(socket.send(&[0; 2]), Err(SendError::RingFull));
+
+    slot.packet_mut()[..3].copy_from_slice(&[1, 2, 3]);
+    slot.commit(3);
+
+    socket.send(&[4; 2]).unwrap();
+    assert_eq!(socket.flush(), 2);
+    assert_eq!(socket.complete_tx(), 2);
+    assert_eq!(socket.stats().tx_packets, 2);
+}
+
+#[test]
+fn dropped_slot_is_aborted() {
+    let socket = common::socket(2, 8);
+
+    let slot = socket.reserve_tx().unwrap();
+    assert_eq!(slot.idx(), 0);
+    drop(slot);
+    assert_eq!(socket.flush(), 0);
+
+    // The aborted slot is reused by the next reservation
+    let slot = socket.reserve_tx().unwrap();
+    assert_eq!(slot.idx(), 0);
+    slot.commit(0);
+    assert_eq!(socket.flush(), 1);
+}
+
+#[test]
+#[should_panic]
+fn commit_beyond_the_frame_panics() {
+    let socket = common::socket(1, 4);
+
+    socket.reserve_tx().unwrap().commit(5);
+}
@@ -0,0 +1,88 @@
+//! Loopback UDP backend tests.
+//!
+//! Miri cannot emulate sockets, so these tests are ignored under Miri.
+
+mod common;
+
+use std::net::{Ipv4Addr, UdpSocket};
+use std::thread;
+
+use rust_nethuns_miri::{RecvError, Socket, UdpBackend};
+
+
+fn recv_one(socket: &Socket<UdpBackend>) -> Vec<u8> {
+    loop {
+        match socket.try_recv() {
+            Ok(packet) => return packet.packet().to_vec(),
+            Err(RecvError::WouldBlock) => thread::yield_now(),
+            Err(err) => panic!("{err}"),
+        }
+    }
+}
+
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_cross_a_port_pair() {
+    // Both sockets are bound before being connected, so that no port
+    // is released and picked again in between
+    let a = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let b = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    a.connect(b.local_addr().unwrap()).unwrap();
+    b.connect(a.local_addr().unwrap()).unwrap();
+    let (a, b) = (UdpBackend::new(a), UdpBackend::new(b));
+    assert_eq!(a.peer_addr().unwrap(), b.local_addr().unwrap());
+    let a = common::socket_over(a, 4, 16);
+    let b = common::socket_over(b, 4, 16);
+
+    for i in 0..3u8 {
+        a.send(&[i; 5]).unwrap();
+    }
+    assert_eq!(a.flush(), 3);
+    assert_eq!(a.stats().tx_dropped, 0);
+
+    for i in 0..3u8 {
+        assert_eq!(recv_one(&b), [i; 5]);
+    }
+    assert!(matches!(b.try_recv(), Err(RecvError::WouldBlock)));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn oversized_datagrams_are_truncated() {
+    let harness = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let backend =
+        UdpBackend::loopback(0, harness.local_addr().unwrap().port()).unwrap();
+    harness.connect(backend.local_addr().unwrap()).unwrap();
+    let socket = common::socket_over(backend, 4, 16);
+
+    harness.send(&[7; 1000]).unwrap();
+    let packet = loop {
+        match socket.try_recv() {
+            Ok(packet) => break packet,
+            Err(RecvError::WouldBlock) => thread::yield_now(),
+            Err(err) => panic!("{err}"),
+        }
+    };
+    assert_eq!(packet.packet(), &[7; 16]);
+    assert_eq!((packet.header().caplen, packet.header().len), (16, 1000));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn talks_to_a_plain_udp_harness() {
+    let harness = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+    let backend =
+        UdpBackend::loopback(0, harness.local_addr().unwrap().port()).unwrap();
+    harness.connect(backend.local_addr().unwrap()).unwrap();
+    let socket = common::socket_over(backend, 4, 16);
+
+    harness.send(b"frame").unwrap();
+    assert_eq!(recv_one(&socket), b"frame");
+
+    socket.send(b"reply").unwrap();
+    socket.flush();
+    let mut buf = [0; 16];
+    let len = harness.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"reply");
+}
@@ -0,0 +1,105 @@
+//! Unix datagram backend tests.
+//!
+//! Miri cannot emulate sockets, so these tests are ignored under Miri.
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::net::UnixDatagram;
+use std::{fs, process, thread};
+
+use rust_nethuns_miri::{RecvError, UnixDatagramBackend};
+
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_cross_a_socket_pair() {
+    let (a, b) = UnixDatagramBackend::pair().unwrap();
+    let a = common::socket_over(a, 4, 16);
+    let b = common::socket_over(b, 4, 16);
+
+    assert!(matches!(b.try_recv(), Err(RecvError::WouldBlock)));
+    for i in 0..3u8 {
+        a.send(&[i; 4]).unwrap();
+    }
+    assert_eq!(a.flush(), 3);
+    assert_eq!(a.stats().tx_dropped, 0);
+
+    for i in 0..3u8 {
+        let packet = b.recv().unwrap();
+        assert_eq!(packet.packet(), &[i; 4]);
+        assert_eq!(packet.header().len, 4);
+    }
+    assert!(matches!(b.try_recv(), Err(RecvError::WouldBlock)));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn oversized_datagrams_are_truncated() {
+    let (a, b) = UnixDatagram::pair().unwrap();
+    let b = common::socket_over(UnixDatagramBackend::new(b), 4, 16);
+
+    a.send(&[7; 40]).unwrap();
+    a.send(&[8; 16]).unwrap();
+    let packet = b.recv().unwrap();
+    assert_eq!(packet.packet(), &[7; 16]);
+    assert_eq!((packet.header().caplen, packet.header().len), (16, 40));
+    drop(packet);
+    let packet = b.recv().unwrap();
+    assert_eq!((packet.header().caplen, packet.header().len), (16, 16));
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn bound_sockets_talk_across_threads() {
+    let dir = std::env::temp_dir()
+        .join(format!("rust-nethuns-unix-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (path_a, path_b) = (dir.join("a.sock"), dir.join("b.sock"));
+
+    // Bind the receiving side first, then connect both
+    let b = UnixDatagram::bind(&path_b).unwrap();
+    let a = UnixDatagramBackend::bind(&path_a, &path_b).unwrap();
+    b.connect(&path_a).unwrap();
+    let a = common::socket_over(a, 4, 16);
+    let b = common::socket_over(UnixDatagramBackend::new(b), 4, 16);
+
+    thread::scope(|s| {
+        s.spawn(move || {
+            let packet = loop {
+                match b.try_recv() {
+                    Ok(packet) => break packet,
+                    Err(RecvError::WouldBlock) => thread::yield_now(),
+                    Err(err) => panic!("{err}"),
+                }
+            };
+            b.send(packet.packet()).unwrap();
+            b.flush();
+        });
+        a.send(b"ping").unwrap();
+        a.flush();
+    });
+
+    let echo = loop {
+        match a.try_recv() {
+            Ok(packet) => break packet.packet().to_vec(),
+            Err(RecvError::WouldBlock) => thread::yield_now(),
+            Err(err) => panic!("{err}"),
+        }
+    };
+    assert_eq!(echo, b"ping");
+    drop(a);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_without_a_peer_are_dropped() {
+    let (a, b) = UnixDatagramBackend::pair().unwrap();
+    drop(b);
+    let a = common::socket_over(a, 4, 16);
+
+    a.send(&[1]).unwrap();
+    assert_eq!(a.flush(), 1);
+    assert_eq!(a.stats().tx_dropped, 1);
+}
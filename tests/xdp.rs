@@ -0,0 +1,170 @@
+//! AF_XDP UMEM tests, meant to be run with `cargo +nightly miri test`.
+
+mod common;
+
+use std::cell::OnceCell;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::rc::{Rc, Weak};
+use std::thread;
+
+use rust_nethuns_miri::{
+    Backend, PacketHeader, RecvError, SlotState, Socket, SocketOptions,
+    VirtualLink, XdpSocket,
+};
+
+
+/// Frames of 6 bytes, spread 8 bytes apart.
+fn opts() -> SocketOptions {
+    common::builder(4, 6).frame_align(8).build().unwrap()
+}
+
+
+/// Backend which calls back into the socket which owns it.
+#[derive(Default)]
+struct ReentrantBackend {
+    socket: Rc<OnceCell<Weak<XdpSocket<ReentrantBackend>>>>,
+}
+
+impl ReentrantBackend {
+    fn socket(&self) -> Rc<XdpSocket<ReentrantBackend>> {
+        self.socket.get().unwrap().upgrade().unwrap()
+    }
+}
+
+impl Backend for ReentrantBackend {
+    fn fill(
+        &mut self,
+        _frame: &mut [u8],
+        _header: &mut PacketHeader,
+    ) -> Result<(), RecvError> {
+        self.socket().refill();
+        Ok(())
+    }
+
+    fn transmit(&mut self, _frame: &[u8]) -> io::Result<()> {
+        self.socket().complete();
+        Ok(())
+    }
+}
+
+
+#[test]
+fn frames_cycle_through_the_four_rings() {
+    let socket = XdpSocket::new_with(opts());
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    assert_eq!(socket.refill(), 4);
+    assert_eq!(socket.frame_state(0), Some(SlotState::Backend));
+
+    let frames: Vec<_> = (0..4).map(|_| socket.recv().unwrap()).collect();
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+    assert_eq!(
+        frames.iter().map(|f| f.addr()).collect::<Vec<_>>(),
+        [0, 8, 16, 24]
+    );
+    assert!(frames.iter().all(|f| f.data() == [0, 1, 2, 3, 4, 5]));
+
+    // Forward two frames, free the other two
+    let mut frames = frames.into_iter();
+    for mut frame in frames.by_ref().take(2) {
+        frame.set_len(2);
+        socket.send(frame).unwrap();
+    }
+    drop(frames);
+    assert_eq!(socket.frame_state(0), Some(SlotState::InFlight));
+    assert_eq!(socket.frame_state(16), Some(SlotState::Free));
+
+    // Only the freed frames can be refilled
+    assert_eq!(socket.refill(), 2);
+    assert_eq!(socket.flush(), 2);
+    assert_eq!(socket.frame_state(8), Some(SlotState::PendingRelease));
+    assert_eq!(socket.completion_len(), 2);
+    assert_eq!(socket.complete(), 2);
+    assert_eq!(socket.refill(), 2);
+    assert_eq!(socket.fill_len(), 4);
+
+    let stats = socket.stats();
+    assert_eq!(stats.rx_packets, 4);
+    assert_eq!(stats.tx_packets, 2);
+}
+
+#[test]
+fn frames_built_in_place_reach_a_socket() {
+    let (a, b) = VirtualLink::new().pair();
+    let xdp = XdpSocket::with_backend(opts(), a).unwrap();
+    let socket = Socket::with_backend(opts(), b).unwrap();
+
+    let mut frame = xdp.alloc_tx().unwrap();
+    assert_eq!(frame.len(), 6);
+    frame.set_len(3);
+    frame.data_mut().copy_from_slice(b"xdp");
+    xdp.send(frame).unwrap();
+    // Nothing is transmitted before the flush
+    assert!(matches!(socket.recv(), Err(RecvError::WouldBlock)));
+
+    assert_eq!(xdp.flush(), 1);
+    assert_eq!(socket.recv().unwrap().packet(), b"xdp");
+    assert_eq!(xdp.complete(), 1);
+}
+
+#[test]
+fn frames_freed_by_workers_are_refilled() {
+    let socket = XdpSocket::new_with(opts());
+    socket.refill();
+
+    thread::scope(|s| {
+        for _ in 0..4 {
+            let frame = socket.recv().unwrap();
+            s.spawn(move || {
+                assert_eq!(frame.data()[5], 5);
+                drop(frame);
+            });
+        }
+    });
+
+    assert_eq!(socket.refill(), 4);
+    assert!(socket.recv().is_ok());
+}
+
+#[test]
+fn foreign_frames_are_rejected() {
+    let socket = XdpSocket::new_with(opts());
+    let other = XdpSocket::new_with(opts());
+    // Addresses which are not the start of a UMEM frame
+    assert_eq!(socket.frame_state(4), None);
+    assert_eq!(socket.frame_state(32), None);
+    assert_eq!(socket.frame_state(u64::MAX), None);
+
+    let frame = other.alloc_tx().unwrap();
+    let frame = socket.send(frame).unwrap_err();
+    assert_eq!(socket.frame_state(0), Some(SlotState::Free));
+    other.send(frame).unwrap();
+    assert_eq!(other.frame_state(0), Some(SlotState::InFlight));
+}
+
+#[test]
+fn backend_cannot_reenter_its_socket() {
+    let backend = ReentrantBackend::default();
+    let handle = Rc::clone(&backend.socket);
+    let socket = Rc::new(XdpSocket::with_backend(opts(), backend).unwrap());
+    handle.set(Rc::downgrade(&socket)).unwrap();
+
+    let reenters = |f: &dyn Fn()| {
+        let err = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_err();
+        let msg = err.downcast_ref::<&str>().unwrap();
+        assert!(msg.starts_with("socket re-entered"), "{msg}");
+    };
+    let frame = socket.alloc_tx().unwrap();
+    socket.send(frame).unwrap();
+    reenters(&|| {
+        socket.flush();
+    });
+    // The frame being transmitted is lost with the backend
+    assert_eq!(socket.refill(), 3);
+    reenters(&|| {
+        let _ = socket.recv();
+    });
+
+    // The socket is usable again once the backend has failed
+    assert_eq!(socket.fill_len(), 3);
+}